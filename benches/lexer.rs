@@ -0,0 +1,76 @@
+//! Compares lexing into a `Vec<Token>` before parsing with feeding the
+//! parser from the `Lexer` iterator directly, and counts the allocations of
+//! lexing alone, which should be none since tokens borrow from the input.
+//!
+//! Run with `cargo bench --bench lexer`.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use myparse::lexer::Lexer;
+use myparse::parser::{parse, parse_tokens};
+
+struct CountingAlloc;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+fn measure(name: &str, iters: usize, mut f: impl FnMut()) {
+    let allocs = ALLOCS.load(Ordering::Relaxed);
+    let bytes = BYTES.load(Ordering::Relaxed);
+    let start = Instant::now();
+    for _ in 0..iters {
+        f();
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<10} {:>10.1} us/iter {:>10} allocs/iter {:>12} bytes/iter",
+        name,
+        elapsed.as_secs_f64() * 1e6 / iters as f64,
+        (ALLOCS.load(Ordering::Relaxed) - allocs) / iters,
+        (BYTES.load(Ordering::Relaxed) - bytes) / iters,
+    );
+}
+
+fn main() {
+    let input = "1 + 2 * (3 - 4) / -5 - ".repeat(10_000) + "6";
+    let iters = 20;
+
+    measure("vec", iters, || {
+        let tokens = Lexer::new(&input).lex().unwrap();
+        parse(tokens).unwrap();
+    });
+    measure("streaming", iters, || {
+        parse_tokens(Lexer::new(&input), None).unwrap();
+    });
+
+    let symbols = "x <+> abs y |> km / h *** speed - ".repeat(10_000) + "z";
+    measure("lex", iters, || {
+        for token in Lexer::new(&symbols) {
+            black_box(token.unwrap());
+        }
+    });
+}
@@ -0,0 +1,140 @@
+//! Completion of partially typed input
+//!
+//! The names offered are the declaration keywords, the built-in functions,
+//! the references to previous results and the operators declared in an
+//! `Interpreter`, plus the commands of the caller after a `:` at the start of
+//! the line.
+//!
+//! ```
+//! use myparse::complete::complete;
+//! use myparse::interpreter::Interpreter;
+//!
+//! let completion = complete("infixr 6 <+> (a, b) = a + b", 3, &Interpreter::new(), &[]);
+//! assert_eq!(completion.start, 0);
+//! assert_eq!(completion.candidates, vec!["infix", "infixl", "infixr"]);
+//! ```
+
+use super::interpreter::Interpreter;
+use super::lexer::is_symbol_char;
+use super::parser::Func;
+
+const KEYWORDS: &[&str] = &["infix", "infixl", "infixr", "postfix", "prefix"];
+
+/// Candidates to replace the text before the cursor with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Completion {
+    /// Byte offset of the start of the replaced text
+    pub start: usize,
+    /// Sorted candidates, all starting with the replaced text
+    pub candidates: Vec<String>,
+}
+
+/// Complete the word before the byte offset `cursor` of `line`
+pub fn complete(line: &str, cursor: usize, interp: &Interpreter, commands: &[&str]) -> Completion {
+    let before = &line[..cursor];
+    let trimmed = before.trim_start();
+    if let Some(name) = trimmed.strip_prefix(':') {
+        let candidates = if name.contains(char::is_whitespace) {
+            Vec::new()
+        } else {
+            commands
+                .iter()
+                .filter(|command| command.starts_with(name))
+                .map(|command| format!(":{}", command))
+                .collect()
+        };
+        return Completion {
+            start: cursor - trimmed.len(),
+            candidates,
+        };
+    }
+
+    let start = word_start(before);
+    let word = &before[start..];
+    let mut candidates: Vec<String> = if word.starts_with('$') {
+        (1..=interp.results().len())
+            .map(|n| format!("${}", n))
+            .filter(|name| name.starts_with(word))
+            .collect()
+    } else if word.starts_with(is_symbol_char) {
+        interp
+            .operators()
+            .filter(|symbol| symbol.starts_with(word))
+            .map(String::from)
+            .collect()
+    } else {
+        // keywords only start a declaration, results exist once evaluated
+        let keywords = if before[..start].trim().is_empty() {
+            KEYWORDS
+        } else {
+            &[]
+        };
+        let results: &[&str] = if interp.results().is_empty() {
+            &[]
+        } else {
+            &["_", "ans"]
+        };
+        let funcs = Func::ALL.iter().map(|func| func.name());
+        keywords
+            .iter()
+            .chain(results)
+            .copied()
+            .chain(funcs)
+            .filter(|name| name.starts_with(word))
+            .map(|name| name.to_string())
+            .collect()
+    };
+    candidates.sort();
+    candidates.dedup();
+    Completion { start, candidates }
+}
+
+/// Start of the identifier, result reference or operator symbol ending
+/// `before`
+fn word_start(before: &str) -> usize {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let start = match before.chars().next_back() {
+        Some(c) if is_symbol_char(c) => before.trim_end_matches(is_symbol_char).len(),
+        _ => before.trim_end_matches(is_word).len(),
+    };
+    let digits = before[start..].bytes().all(|b| b.is_ascii_digit());
+    if digits && before[..start].ends_with('$') {
+        start - 1
+    } else {
+        start
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::{parse_stmt_with, ParseConfig, Stmt};
+
+    #[test]
+    fn test_complete() {
+        let mut interp = Interpreter::new();
+        let config = ParseConfig::new();
+        for s in &["infixl 6 <+> (a, b) = a + b", "prefix <- (x) = -x"] {
+            match parse_stmt_with(Lexer::new(s), &config, None).unwrap() {
+                Stmt::OpDecl(decl) => interp.define(&decl),
+                Stmt::Expr(_) => unreachable!(),
+            }
+        }
+        interp.record(1.into());
+        interp.record(2.into());
+
+        let complete = |s: &str| {
+            let completion = complete(s, s.len(), &interp, &["help", "load", "ast"]);
+            (completion.start, completion.candidates)
+        };
+        let strings = |v: &[&str]| v.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(complete("1 <"), (2, strings(&["<+>", "<-"])));
+        assert_eq!(complete("1 + $"), (4, strings(&["$1", "$2"])));
+        assert_eq!(complete("1+a"), (2, strings(&["abs", "ans", "arg"])));
+        assert_eq!(complete("pr"), (0, strings(&["prefix"])));
+        assert_eq!(complete("1 + pr"), (4, strings(&[])));
+        assert_eq!(complete("  :a"), (2, strings(&[":ast"])));
+        assert_eq!(complete(":load x"), (0, strings(&[])));
+    }
+}
@@ -0,0 +1,105 @@
+//! Complex numbers, made by imaginary literals such as `2.5i`
+//!
+//! ```
+//! use myparse::complex::Complex;
+//!
+//! let z = Complex::new(3.0, 4.0);
+//! assert_eq!(z * z.conj(), Complex::new(25.0, 0.0));
+//! assert_eq!(z.abs(), 5.0);
+//! ```
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub const ZERO: Complex = Complex { re: 0.0, im: 0.0 };
+
+    pub fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    pub fn conj(self) -> Self {
+        Complex::new(self.re, -self.im)
+    }
+
+    /// Modulus
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    /// Argument in radians, in `(-pi, pi]`
+    pub fn arg(self) -> f64 {
+        self.im.atan2(self.re)
+    }
+}
+
+impl From<f64> for Complex {
+    fn from(re: f64) -> Self {
+        Complex::new(re, 0.0)
+    }
+}
+
+impl Neg for Complex {
+    type Output = Complex;
+    fn neg(self) -> Complex {
+        Complex::new(-self.re, -self.im)
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+    /// Quotient, infinite or NaN if `other` is zero
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn div(self, other: Complex) -> Complex {
+        let den = other.re * other.re + other.im * other.im;
+        let num = self * other.conj();
+        Complex::new(num.re / den, num.im / den)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arithmetic() {
+        let (z, w) = (Complex::new(1.0, 2.0), Complex::new(3.0, -1.0));
+        assert_eq!(z + w, Complex::new(4.0, 1.0));
+        assert_eq!(z - w, Complex::new(-2.0, 3.0));
+        assert_eq!(z * w, Complex::new(5.0, 5.0));
+        assert_eq!((z * w) / w, z);
+        assert_eq!(-z, Complex::new(-1.0, -2.0));
+        assert_eq!(Complex::new(0.0, 1.0).arg(), std::f64::consts::FRAC_PI_2);
+        assert_eq!(Complex::from(-2.0).arg(), std::f64::consts::PI);
+    }
+}
@@ -0,0 +1,102 @@
+//! Debug views of tokens, `Ast`s and parser traces
+
+use std::fmt::Write;
+
+use super::lexer::{Loc, Token};
+use super::parser::{Ast, AstNode, ParseError, Tracer};
+use super::visit::{Step, Walk};
+
+/// List `tokens` one per line with their spans
+pub fn dump_tokens(tokens: &[Token]) -> String {
+    let mut buf = String::new();
+    for token in tokens {
+        writeln!(buf, "{:<8}{:?}", token.loc.to_string(), token.value).unwrap();
+    }
+    buf
+}
+
+/// Nesting beyond which lines are not indented any further, so that the
+/// dump of a deep tree stays linear in its size
+const MAX_INDENT: usize = 32;
+
+/// Render `ast` as an indented tree with the span of every node
+///
+/// Lines nested deeper than `MAX_INDENT` levels start with their depth in
+/// brackets instead.
+pub fn dump_ast(ast: &Ast) -> String {
+    let mut buf = String::new();
+    let mut depth = 0;
+    for step in Walk::new(ast) {
+        match step {
+            Step::Enter(ast) => {
+                let label = match ast.value {
+                    AstNode::Num(n) => format!("Num {}", n),
+                    AstNode::Decimal(d) => format!("Decimal {}", d),
+                    AstNode::Imaginary(d) => format!("Imaginary {}", d),
+                    AstNode::Var(ref name) => format!("Var {}", name),
+                    AstNode::UniOp { ref op, .. } => {
+                        format!("UniOp {:?} (op @ {})", op.value, op.loc)
+                    }
+                    AstNode::BinOp { ref op, .. } => {
+                        format!("BinOp {:?} (op @ {})", op.value, op.loc)
+                    }
+                };
+                buf.push_str(&"  ".repeat(depth.min(MAX_INDENT)));
+                if depth > MAX_INDENT {
+                    write!(buf, "[{}] ", depth).unwrap();
+                }
+                writeln!(buf, "{} @ {}", label, ast.loc).unwrap();
+                depth += 1;
+            }
+            Step::Leave(_) => depth -= 1,
+        }
+    }
+    buf
+}
+
+/// `Tracer` printing the parser rules as they are entered and left to stderr
+#[derive(Debug, Default)]
+pub struct StderrTracer {
+    depth: usize,
+}
+
+impl Tracer for StderrTracer {
+    fn enter(&mut self, rule: &'static str, next: Option<&Token>) {
+        let next = next.map_or("EOF".to_string(), |token| token.value.to_string());
+        eprintln!("{}{} -- next: {}", "  ".repeat(self.depth), rule, next);
+        self.depth += 1;
+    }
+
+    fn exit(&mut self, rule: &'static str, result: Result<&Loc, &ParseError>) {
+        self.depth -= 1;
+        match result {
+            Ok(loc) => eprintln!("{}{}: ok @ {}", "  ".repeat(self.depth), rule, loc),
+            Err(err) => eprintln!("{}{}: {}", "  ".repeat(self.depth), rule, err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dump_ast() {
+        let ast = "1 + -(2 * 3)".parse::<Ast>().unwrap();
+        assert_eq!(
+            dump_ast(&ast),
+            "BinOp Add (op @ 2-3) @ 0-11\n\
+             \x20 Num 1 @ 0-1\n\
+             \x20 UniOp Minus (op @ 4-5) @ 4-11\n\
+             \x20   BinOp Mul (op @ 8-9) @ 6-11\n\
+             \x20     Num 2 @ 6-7\n\
+             \x20     Num 3 @ 10-11\n"
+        );
+
+        let deep = format!("{}1", "1+".repeat(100_000)).parse::<Ast>().unwrap();
+        let dump = dump_ast(&deep);
+        assert_eq!(dump.lines().count(), 200_001);
+        let deepest = dump.lines().nth(100_000).unwrap();
+        assert_eq!(deepest, format!("{}[100000] Num 1 @ 0-1", "  ".repeat(32)));
+    }
+}
@@ -0,0 +1,451 @@
+//! Export of `Ast`s as Graphviz DOT and JSON, and import from JSON
+//!
+//! The JSON schema is stable:
+//!
+//! ```text
+//! Loc   = {"start": UINT, "end": UINT}
+//! UniOp = {"kind": "Plus" | "Minus", "loc": Loc}
+//!       | {"kind": "Func", "symbol": "re" | "im" | "abs" | "arg" | "conj", "loc": Loc}
+//!       | {"kind": "Custom", "symbol": STRING, "loc": Loc}
+//! BinOp = {"kind": "Add" | "Sub" | "Mul" | "Div" | "Convert", "loc": Loc}
+//!       | {"kind": "Custom", "symbol": STRING, "loc": Loc}
+//! Ast   = {"type": "Num", "value": UINT, "loc": Loc}
+//!       | {"type": "Decimal", "value": STRING, "loc": Loc}
+//!       | {"type": "Imaginary", "value": STRING, "loc": Loc}
+//!       | {"type": "Var", "name": STRING, "loc": Loc}
+//!       | {"type": "UniOp", "op": UniOp, "e": Ast, "loc": Loc}
+//!       | {"type": "BinOp", "op": BinOp, "l": Ast, "r": Ast, "loc": Loc}
+//! ```
+
+use std::fmt;
+use std::fmt::Write;
+use std::str::FromStr;
+
+use super::json::{self, Json, JsonError};
+use super::lexer::{Lexer, Loc, Token, TokenKind};
+use super::parser::{Ast, AstNode, BinOp, BinOpKind, Func, UniOp, UniOpKind};
+use super::visit::{Step, Walk};
+
+/// Output formats selectable with `--emit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    Dot,
+    AstJson,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dot" => Ok(Emit::Dot),
+            "ast-json" => Ok(Emit::AstJson),
+            _ => Err(format!("unknown emit format '{}'", s)),
+        }
+    }
+}
+
+impl Emit {
+    pub fn emit(self, ast: &Ast) -> String {
+        match self {
+            Emit::Dot => to_dot(ast),
+            Emit::AstJson => to_json(ast).to_string(),
+        }
+    }
+}
+
+/// Render `ast` as a Graphviz digraph
+pub fn to_dot(ast: &Ast) -> String {
+    let mut buf = String::new();
+    writeln!(buf, "digraph ast {{").unwrap();
+    writeln!(buf, "    node [shape=box];").unwrap();
+    // ids of the ancestors of the node being visited, with the labels of
+    // the edges to their children not visited yet
+    let mut parents: Vec<(usize, &[&str])> = Vec::new();
+    let mut next_id = 0;
+    for step in Walk::new(ast) {
+        let ast = match step {
+            Step::Enter(ast) => ast,
+            Step::Leave(ast) => {
+                if let AstNode::UniOp { .. } | AstNode::BinOp { .. } = ast.value {
+                    parents.pop();
+                }
+                continue;
+            }
+        };
+        let (label, edges): (_, &[&str]) = match ast.value {
+            AstNode::Num(n) => (n.to_string(), &[]),
+            AstNode::Decimal(d) => (d.to_string(), &[]),
+            AstNode::Imaginary(d) => (format!("{}i", d), &[]),
+            AstNode::Var(ref name) => (name.clone(), &[]),
+            AstNode::UniOp { ref op, .. } => (format!("{:?} @ {}", op.value, op.loc), &["e"]),
+            AstNode::BinOp { ref op, .. } => (format!("{:?} @ {}", op.value, op.loc), &["l", "r"]),
+        };
+        let id = next_id;
+        next_id += 1;
+        let label = format!("{}\\n{}", label, ast.loc).replace('"', "\\\"");
+        writeln!(buf, "    n{} [label=\"{}\"];", id, label).unwrap();
+        if let Some((parent, labels)) = parents.last_mut() {
+            writeln!(buf, "    n{} -> n{} [label=\"{}\"];", parent, id, labels[0]).unwrap();
+            *labels = &labels[1..];
+        }
+        if !edges.is_empty() {
+            parents.push((id, edges));
+        }
+    }
+    writeln!(buf, "}}").unwrap();
+    buf
+}
+
+fn loc_to_json(loc: &Loc) -> Json {
+    Json::Object(vec![
+        ("start".to_string(), loc.0.into()),
+        ("end".to_string(), loc.1.into()),
+    ])
+}
+
+/// Convert `ast` to the JSON schema described in the module documentation
+pub fn to_json(ast: &Ast) -> Json {
+    // the converted children of the nodes being visited, in order
+    let mut values = Vec::new();
+    for step in Walk::new(ast) {
+        let ast = match step {
+            Step::Enter(_) => continue,
+            Step::Leave(ast) => ast,
+        };
+        let mut fields = Vec::new();
+        match ast.value {
+            AstNode::Num(n) => {
+                fields.push(("type".to_string(), "Num".into()));
+                fields.push(("value".to_string(), n.into()));
+            }
+            AstNode::Decimal(d) => {
+                fields.push(("type".to_string(), "Decimal".into()));
+                fields.push(("value".to_string(), d.to_string()[..].into()));
+            }
+            AstNode::Imaginary(d) => {
+                fields.push(("type".to_string(), "Imaginary".into()));
+                fields.push(("value".to_string(), d.to_string()[..].into()));
+            }
+            AstNode::Var(ref name) => {
+                fields.push(("type".to_string(), "Var".into()));
+                fields.push(("name".to_string(), name[..].into()));
+            }
+            AstNode::UniOp { ref op, .. } => {
+                let (kind, symbol) = match op.value {
+                    UniOpKind::Plus => ("Plus", None),
+                    UniOpKind::Minus => ("Minus", None),
+                    UniOpKind::Func(func) => ("Func", Some(func.name())),
+                    UniOpKind::Custom(ref symbol) => ("Custom", Some(&symbol[..])),
+                };
+                let e = values.pop().unwrap();
+                fields.push(("type".to_string(), "UniOp".into()));
+                fields.push(("op".to_string(), op_to_json(kind, symbol, &op.loc)));
+                fields.push(("e".to_string(), e));
+            }
+            AstNode::BinOp { ref op, .. } => {
+                let (kind, symbol) = match op.value {
+                    BinOpKind::Add => ("Add", None),
+                    BinOpKind::Sub => ("Sub", None),
+                    BinOpKind::Mul => ("Mul", None),
+                    BinOpKind::Div => ("Div", None),
+                    BinOpKind::Convert => ("Convert", None),
+                    BinOpKind::Custom(ref symbol) => ("Custom", Some(&symbol[..])),
+                };
+                let r = values.pop().unwrap();
+                let l = values.pop().unwrap();
+                fields.push(("type".to_string(), "BinOp".into()));
+                fields.push(("op".to_string(), op_to_json(kind, symbol, &op.loc)));
+                fields.push(("l".to_string(), l));
+                fields.push(("r".to_string(), r));
+            }
+        }
+        fields.push(("loc".to_string(), loc_to_json(&ast.loc)));
+        values.push(Json::Object(fields));
+    }
+    values.pop().unwrap()
+}
+
+fn op_to_json(kind: &str, symbol: Option<&str>, loc: &Loc) -> Json {
+    let mut fields = vec![("kind".to_string(), kind.into())];
+    if let Some(symbol) = symbol {
+        fields.push(("symbol".to_string(), symbol.into()));
+    }
+    fields.push(("loc".to_string(), loc_to_json(loc)));
+    Json::Object(fields)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FromJsonError {
+    Json(JsonError),
+    /// A field is missing or has a wrong type; holds the path to the field
+    InvalidField(String),
+}
+
+impl From<JsonError> for FromJsonError {
+    fn from(e: JsonError) -> Self {
+        FromJsonError::Json(e)
+    }
+}
+
+impl std::error::Error for FromJsonError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FromJsonError::Json(err) => Some(err),
+            FromJsonError::InvalidField(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for FromJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FromJsonError::Json(_) => write!(f, "invalid JSON"),
+            FromJsonError::InvalidField(path) => write!(f, "{}: missing or invalid field", path),
+        }
+    }
+}
+
+/// Read an `Ast` from a JSON document
+pub fn ast_from_json_str(s: &str) -> Result<Ast, FromJsonError> {
+    from_json(&json::parse(s)?, "$")
+}
+
+/// Build an `Ast` from a JSON value; `path` names `json` in error messages
+pub fn from_json(json: &Json, path: &str) -> Result<Ast, FromJsonError> {
+    // the nodes are read top-down with an explicit stack, then built
+    // bottom-up from the last one read; every node keeps the index of its
+    // parent and the field it is in, from which the path to it is only built
+    // on error
+    let mut pending = vec![(json, None)];
+    let mut parents = Vec::new();
+    let mut nodes = Vec::new();
+    while let Some((json, parent)) = pending.pop() {
+        let index = parents.len();
+        parents.push(parent);
+        let node = node_from_json(json, "").map_err(|err| match err {
+            FromJsonError::InvalidField(field) => {
+                FromJsonError::InvalidField(node_path(path, &parents, index) + &field)
+            }
+            err => err,
+        })?;
+        match node {
+            Node::Leaf(_) => {}
+            Node::UniOp(_, e, _) => pending.push((e, Some((index, "e")))),
+            Node::BinOp(_, l, r, _) => {
+                pending.push((r, Some((index, "r"))));
+                pending.push((l, Some((index, "l"))));
+            }
+        }
+        nodes.push(node);
+    }
+
+    let mut asts = Vec::new();
+    for node in nodes.into_iter().rev() {
+        let ast = match node {
+            Node::Leaf(ast) => ast,
+            Node::UniOp(op, _, loc) => Ast::uniop(op, asts.pop().unwrap(), loc),
+            Node::BinOp(op, _, _, loc) => {
+                let l = asts.pop().unwrap();
+                let r = asts.pop().unwrap();
+                Ast::binop(op, l, r, loc)
+            }
+        };
+        asts.push(ast);
+    }
+    Ok(asts.pop().unwrap())
+}
+
+/// Path to the node `index` read by `from_json`, from the parents of the
+/// nodes and the fields they are in
+fn node_path(root: &str, parents: &[Option<(usize, &str)>], mut index: usize) -> String {
+    let mut fields = Vec::new();
+    while let Some((parent, field)) = parents[index] {
+        fields.push(field);
+        index = parent;
+    }
+    let mut path = root.to_string();
+    for field in fields.into_iter().rev() {
+        path.push('.');
+        path.push_str(field);
+    }
+    path
+}
+
+/// A node read from JSON, holding its children still to be converted
+enum Node<'j> {
+    Leaf(Ast),
+    UniOp(UniOp, &'j Json, Loc),
+    BinOp(BinOp, &'j Json, &'j Json, Loc),
+}
+
+fn node_from_json<'j>(json: &'j Json, path: &str) -> Result<Node<'j>, FromJsonError> {
+    let field = |key: &str| {
+        json.get(key)
+            .ok_or_else(|| FromJsonError::InvalidField(format!("{}.{}", path, key)))
+    };
+    let invalid = |key: &str| FromJsonError::InvalidField(format!("{}.{}", path, key));
+    let loc = loc_from_json(field("loc")?, &format!("{}.loc", path))?;
+    match field("type")?.as_str() {
+        Some("Num") => {
+            let n = field("value")?.as_u64().ok_or_else(|| invalid("value"))?;
+            Ok(Node::Leaf(Ast::num(n, loc)))
+        }
+        Some("Decimal") => {
+            let value = field("value")?.as_str().ok_or_else(|| invalid("value"))?;
+            match Lexer::new(value).lex().as_deref() {
+                Ok(
+                    [Token {
+                        value: TokenKind::Decimal(d),
+                        ..
+                    }],
+                ) => Ok(Node::Leaf(Ast::new(AstNode::Decimal(*d), loc))),
+                _ => Err(invalid("value")),
+            }
+        }
+        Some("Imaginary") => {
+            let value = field("value")?.as_str().ok_or_else(|| invalid("value"))?;
+            let value = format!("{}i", value);
+            match Lexer::new(&value).lex().as_deref() {
+                Ok(
+                    [Token {
+                        value: TokenKind::Imaginary(d),
+                        ..
+                    }],
+                ) => Ok(Node::Leaf(Ast::new(AstNode::Imaginary(*d), loc))),
+                _ => Err(invalid("value")),
+            }
+        }
+        Some("Var") => {
+            let name = field("name")?.as_str().ok_or_else(|| invalid("name"))?;
+            Ok(Node::Leaf(Ast::var(name, loc)))
+        }
+        Some("UniOp") => {
+            let op_json = field("op")?;
+            let (kind, op_loc) = op_from_json(op_json, &format!("{}.op", path))?;
+            let op = match kind {
+                "Plus" => UniOp::plus(op_loc),
+                "Minus" => UniOp::minus(op_loc),
+                "Func" => match Func::from_name(&symbol_from_json(op_json, path)?) {
+                    Some(func) => UniOp::new(UniOpKind::Func(func), op_loc),
+                    None => return Err(invalid("op.symbol")),
+                },
+                "Custom" => UniOp::new(UniOpKind::Custom(symbol_from_json(op_json, path)?), op_loc),
+                _ => return Err(invalid("op.kind")),
+            };
+            Ok(Node::UniOp(op, field("e")?, loc))
+        }
+        Some("BinOp") => {
+            let op_json = field("op")?;
+            let (kind, op_loc) = op_from_json(op_json, &format!("{}.op", path))?;
+            let op = match kind {
+                "Add" => BinOp::add(op_loc),
+                "Sub" => BinOp::sub(op_loc),
+                "Mul" => BinOp::mul(op_loc),
+                "Div" => BinOp::div(op_loc),
+                "Convert" => BinOp::new(BinOpKind::Convert, op_loc),
+                "Custom" => BinOp::new(BinOpKind::Custom(symbol_from_json(op_json, path)?), op_loc),
+                _ => return Err(invalid("op.kind")),
+            };
+            Ok(Node::BinOp(op, field("l")?, field("r")?, loc))
+        }
+        _ => Err(invalid("type")),
+    }
+}
+
+fn op_from_json<'j>(json: &'j Json, path: &str) -> Result<(&'j str, Loc), FromJsonError> {
+    let kind = json
+        .get("kind")
+        .and_then(Json::as_str)
+        .ok_or_else(|| FromJsonError::InvalidField(format!("{}.kind", path)))?;
+    let loc = json
+        .get("loc")
+        .ok_or_else(|| FromJsonError::InvalidField(format!("{}.loc", path)))?;
+    Ok((kind, loc_from_json(loc, &format!("{}.loc", path))?))
+}
+
+/// Symbol of the custom operator `json`, the `op` field of the node at `path`
+fn symbol_from_json(json: &Json, path: &str) -> Result<String, FromJsonError> {
+    json.get("symbol")
+        .and_then(Json::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| FromJsonError::InvalidField(format!("{}.op.symbol", path)))
+}
+
+fn loc_from_json(json: &Json, path: &str) -> Result<Loc, FromJsonError> {
+    let get = |key: &str| {
+        json.get(key)
+            .and_then(Json::as_u64)
+            .map(|n| n as usize)
+            .ok_or_else(|| FromJsonError::InvalidField(format!("{}.{}", path, key)))
+    };
+    Ok(Loc(get("start")?, get("end")?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_roundtrip() {
+        for s in &["1 + 2 * -3", "1.50 km in m", "conj(1 - 2.5j)"] {
+            let ast = s.parse::<Ast>().unwrap();
+            let json = to_json(&ast).to_string();
+            assert_eq!(ast_from_json_str(&json), Ok(ast));
+        }
+        assert_eq!(
+            ast_from_json_str(
+                r#"{"type": "UniOp", "op": {"kind": "Neg"}, "loc": {"start": 0, "end": 1}}"#
+            ),
+            Err(FromJsonError::InvalidField("$.op.loc".to_string()))
+        );
+
+        let chain = |terms: usize| {
+            format!("{}1", "1+".repeat(terms - 1))
+                .parse::<Ast>()
+                .unwrap()
+        };
+        // whatever their depth, the trees emitted are read back
+        let ast = chain(100_000);
+        assert_eq!(ast_from_json_str(&Emit::AstJson.emit(&ast)), Ok(ast));
+        let json =
+            Emit::AstJson
+                .emit(&chain(1_000))
+                .replacen(r#""type":"Num""#, r#""type":"Int""#, 1);
+        assert_eq!(
+            ast_from_json_str(&json),
+            Err(FromJsonError::InvalidField(format!(
+                "${}.type",
+                ".l".repeat(999)
+            )))
+        );
+        let nested = format!("{}{}", "[".repeat(100_000), "]".repeat(100_000));
+        assert_eq!(
+            ast_from_json_str(&nested),
+            Err(FromJsonError::InvalidField("$.loc".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_dot() {
+        let ast = "1 - 2".parse::<Ast>().unwrap();
+        assert_eq!(
+            to_dot(&ast),
+            "digraph ast {\n\
+             \x20   node [shape=box];\n\
+             \x20   n0 [label=\"Sub @ 2-3\\n0-5\"];\n\
+             \x20   n1 [label=\"1\\n0-1\"];\n\
+             \x20   n0 -> n1 [label=\"l\"];\n\
+             \x20   n2 [label=\"2\\n4-5\"];\n\
+             \x20   n0 -> n2 [label=\"r\"];\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_emit_deep() {
+        let ast = format!("{}1", "1+".repeat(100_000)).parse::<Ast>().unwrap();
+        let dot = Emit::Dot.emit(&ast);
+        assert!(dot.ends_with("    n0 -> n200000 [label=\"r\"];\n}\n"));
+    }
+}
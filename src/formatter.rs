@@ -0,0 +1,261 @@
+//! Formatting of evaluation results
+
+use std::str::FromStr;
+
+use super::interpreter::Value;
+use super::units::Dimension;
+
+/// How non-integer numbers are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Notation {
+    /// Shortest representation that reads back to the same value
+    Auto,
+    /// Fixed number of digits after the decimal point
+    Fixed(usize),
+    /// Scientific notation with a fixed number of digits after the decimal point
+    Scientific(usize),
+}
+
+/// Parse `auto`, `fixed:N` or `sci:N`, where `N` is the number of digits
+/// after the decimal point
+impl FromStr for Notation {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, precision) = match s.split_once(':') {
+            Some((name, digits)) => match digits.parse() {
+                Ok(precision) => (name, Some(precision)),
+                Err(_) => return Err(format!("invalid number of digits '{}'", digits)),
+            },
+            None => (s, None),
+        };
+        match (name, precision) {
+            ("auto", None) => Ok(Notation::Auto),
+            ("fixed", Some(precision)) => Ok(Notation::Fixed(precision)),
+            ("sci", Some(precision)) => Ok(Notation::Scientific(precision)),
+            _ => Err(format!("unknown notation '{}'", s)),
+        }
+    }
+}
+
+/// Number formatting options
+///
+/// ```
+/// use myparse::formatter::Formatter;
+///
+/// let f = Formatter::new().radix(16).grouping(true);
+/// assert_eq!(f.format_int(-0x12345), "-0x1_2345");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Formatter {
+    radix: u32,
+    grouping: bool,
+    notation: Notation,
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Formatter {
+    pub fn new() -> Self {
+        Formatter {
+            radix: 10,
+            grouping: false,
+            notation: Notation::Auto,
+        }
+    }
+
+    /// Set the radix of integers, one of 2, 8, 10 or 16.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `radix` is not supported.
+    pub fn radix(mut self, radix: u32) -> Self {
+        assert!(is_supported_radix(radix), "unsupported radix {}", radix);
+        self.radix = radix;
+        self
+    }
+
+    /// Separate groups of digits: thousands with `,` in base 10 and groups of
+    /// four digits with `_` in the other radices.
+    pub fn grouping(mut self, grouping: bool) -> Self {
+        self.grouping = grouping;
+        self
+    }
+
+    pub fn notation(mut self, notation: Notation) -> Self {
+        self.notation = notation;
+        self
+    }
+
+    /// Format an integer, with the same prefix as the lexer accepts for the
+    /// radix (`0x`, `0o` or `0b`).
+    pub fn format_int(&self, n: i64) -> String {
+        let (prefix, digits) = match self.radix {
+            2 => ("0b", format!("{:b}", n.unsigned_abs())),
+            8 => ("0o", format!("{:o}", n.unsigned_abs())),
+            16 => ("0x", format!("{:x}", n.unsigned_abs())),
+            _ => ("", n.unsigned_abs().to_string()),
+        };
+        let sign = if n < 0 { "-" } else { "" };
+        format!("{}{}{}", sign, prefix, self.group(&digits))
+    }
+
+    /// Format a non-integer number in decimal
+    pub fn format_float(&self, x: f64) -> String {
+        let s = match self.notation {
+            Notation::Auto => x.to_string(),
+            Notation::Fixed(precision) => format!("{:.*}", precision, x),
+            Notation::Scientific(precision) => return format!("{:.*e}", precision, x),
+        };
+        if !x.is_finite() {
+            return s;
+        }
+        let (sign, s) = match s.strip_prefix('-') {
+            Some(s) => ("-", s),
+            None => ("", &s[..]),
+        };
+        let (int, frac) = match s.find('.') {
+            Some(i) => s.split_at(i),
+            None => (s, ""),
+        };
+        let group = Formatter::new().grouping(self.grouping);
+        format!("{}{}{}", sign, group.group(int), frac)
+    }
+
+    /// Format the result of an evaluation, a quantity in decimal followed by
+    /// its unit, or its dimension in base units if it was not converted
+    ///
+    /// Complex numbers are written `a+bi`.
+    pub fn format_value(&self, value: &Value) -> String {
+        let q = match value {
+            Value::Int(n) => return self.format_int(*n),
+            Value::Quantity(q) => q,
+            Value::Complex(z) => {
+                let sign = if z.im < 0.0 { '-' } else { '+' };
+                // adding 0 turns the real part of e.g. `-3i` from -0 to 0
+                return format!(
+                    "{}{}{}i",
+                    self.format_float(round(z.re) + 0.0),
+                    sign,
+                    self.format_float(round(z.im.abs()))
+                );
+            }
+        };
+        let (x, unit) = match q.unit {
+            Some(ref unit) => (q.value / unit.factor, unit.name.clone()),
+            None if q.dim == Dimension::NONE => (q.value, String::new()),
+            None => (q.value, q.dim.to_string()),
+        };
+        let x = round(x);
+        if unit.is_empty() {
+            self.format_float(x)
+        } else {
+            format!("{} {}", self.format_float(x), unit)
+        }
+    }
+
+    fn group(&self, digits: &str) -> String {
+        if !self.grouping {
+            return digits.to_string();
+        }
+        let (size, sep) = if self.radix == 10 { (3, ',') } else { (4, '_') };
+        let mut buf = String::new();
+        for (i, c) in digits.chars().enumerate() {
+            if i > 0 && (digits.len() - i).is_multiple_of(size) {
+                buf.push(sep);
+            }
+            buf.push(c);
+        }
+        buf
+    }
+}
+
+/// Hide the rounding errors of conversions and complex arithmetic, e.g. of
+/// `km/h` or `(1+2i) / (3-1i)`
+fn round(x: f64) -> f64 {
+    format!("{:.14e}", x).parse().unwrap()
+}
+
+pub fn is_supported_radix(radix: u32) -> bool {
+    [2, 8, 10, 16].contains(&radix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_int() {
+        let f = Formatter::new();
+        assert_eq!(f.format_int(-1234567), "-1234567");
+        assert_eq!(f.grouping(true).format_int(-1234567), "-1,234,567");
+        assert_eq!(f.grouping(true).format_int(123), "123");
+        assert_eq!(f.radix(16).format_int(255), "0xff");
+        assert_eq!(f.radix(8).format_int(-8), "-0o10");
+        assert_eq!(f.radix(2).grouping(true).format_int(0b101101), "0b10_1101");
+    }
+
+    #[test]
+    fn test_format_value() {
+        use crate::complex::Complex;
+        use crate::units::{Quantity, UnitName};
+
+        let f = Formatter::new().radix(16);
+        let speed = Quantity::new(2.5 / 3.6, Dimension([1, 0, -1, 0, 0, 0, 0]));
+        assert_eq!(f.format_value(&Value::Int(255)), "0xff");
+        assert_eq!(
+            f.format_value(&Value::Quantity(speed.clone())),
+            "0.694444444444444 m/s"
+        );
+        let unit = UnitName {
+            name: "km/h".to_string(),
+            factor: 1000.0 / 3600.0,
+        };
+        assert_eq!(
+            f.format_value(&Value::Quantity(Quantity {
+                unit: Some(unit),
+                ..speed
+            })),
+            "2.5 km/h"
+        );
+        assert_eq!(
+            f.format_value(&Value::Quantity(Quantity::new(0.5, Dimension::NONE))),
+            "0.5"
+        );
+        let f = Formatter::new();
+        let z = |re, im| Value::Complex(Complex::new(re, im));
+        assert_eq!(f.format_value(&z(1.0, -2.5)), "1-2.5i");
+        assert_eq!(f.format_value(&z(-0.5, 0.0)), "-0.5+0i");
+        assert_eq!(f.format_value(&z(-0.0, -3.0)), "0-3i");
+        assert_eq!(f.format_value(&z(0.1 + 0.2, 3.0)), "0.3+3i");
+    }
+
+    #[test]
+    fn test_format_float() {
+        let f = Formatter::new();
+        assert_eq!(f.format_float(1234.5), "1234.5");
+        assert_eq!(
+            f.grouping(true)
+                .notation(Notation::Fixed(2))
+                .format_float(-1234567.891),
+            "-1,234,567.89"
+        );
+        assert_eq!(
+            f.notation(Notation::Scientific(3)).format_float(1234.5),
+            "1.234e3"
+        );
+    }
+
+    #[test]
+    fn test_parse_notation() {
+        assert_eq!("auto".parse(), Ok(Notation::Auto));
+        assert_eq!("fixed:2".parse(), Ok(Notation::Fixed(2)));
+        assert_eq!("sci:0".parse(), Ok(Notation::Scientific(0)));
+        assert!("fixed".parse::<Notation>().is_err());
+        assert!("auto:1".parse::<Notation>().is_err());
+        assert!("sci:-1".parse::<Notation>().is_err());
+    }
+}
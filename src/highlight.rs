@@ -0,0 +1,147 @@
+//! Syntax highlighting of the REPL input
+
+use super::lexer::{Lexer, Token, TokenKind};
+use super::line_editor::{Highlighter, Style};
+use super::operator::OperatorTable;
+use super::parser::Fixity;
+
+const NUMBER: Style = "36";
+const OPERATOR: Style = "33";
+const IDENT: Style = "32";
+const KEYWORD: Style = "1;34";
+const COMMENT: Style = "90";
+const MATCHING_PAREN: Style = "1;7";
+const ERROR: Style = "4;31";
+
+/// Colors the tokens of a line, the parenthesis matching the one at the
+/// cursor and the first lex error
+pub struct SyntaxHighlighter;
+
+impl Highlighter for SyntaxHighlighter {
+    fn highlight(&self, line: &str, cursor: usize) -> Vec<Style> {
+        let mut tokens = Vec::new();
+        let mut error = None;
+        for ret in Lexer::new(line) {
+            match ret {
+                Ok(token) => tokens.push(token),
+                Err(err) => {
+                    error = Some(err);
+                    break;
+                }
+            }
+        }
+
+        // styles by byte offset, converted to chars at the end
+        let table = OperatorTable::default();
+        let mut styles = vec![""; line.len()];
+        let lexed = error.as_ref().map_or(line.len(), |err| err.loc.0);
+        // anything but spaces between the tokens is a comment
+        for (i, c) in line[..lexed].char_indices() {
+            if !c.is_whitespace() {
+                styles[i..i + c.len_utf8()].fill(COMMENT);
+            }
+        }
+        for (i, token) in tokens.iter().enumerate() {
+            let style = match token.value {
+                TokenKind::Number(_) | TokenKind::Decimal(_) | TokenKind::Imaginary(_) => NUMBER,
+                TokenKind::Plus
+                | TokenKind::Minus
+                | TokenKind::Asterisk
+                | TokenKind::Slash
+                | TokenKind::Op(_) => OPERATOR,
+                // `in`
+                TokenKind::Ident(_)
+                    if table.infix(&token.value).is_some()
+                        || table.prefix(&token.value).is_some() =>
+                {
+                    OPERATOR
+                }
+                TokenKind::Ident(ref name) if i == 0 && Fixity::from_keyword(name).is_some() => {
+                    KEYWORD
+                }
+                TokenKind::Ident(_) => IDENT,
+                TokenKind::LParen | TokenKind::RParen | TokenKind::Comma | TokenKind::Semicolon => {
+                    ""
+                }
+            };
+            styles[token.loc.0..token.loc.1].fill(style);
+        }
+
+        let cursor = line
+            .char_indices()
+            .nth(cursor)
+            .map_or(line.len(), |(i, _)| i);
+        if let Some((open, close)) = matching_parens(&tokens, cursor) {
+            styles[open.loc.0..open.loc.1].fill(MATCHING_PAREN);
+            styles[close.loc.0..close.loc.1].fill(MATCHING_PAREN);
+        }
+        if let Some(err) = error {
+            styles[err.loc.0..err.loc.1.min(line.len())].fill(ERROR);
+        }
+
+        line.char_indices().map(|(i, _)| styles[i]).collect()
+    }
+}
+
+/// The parenthesis at byte offset `cursor`, or else the one just before it,
+/// and its counterpart, in source order
+fn matching_parens<'t, 'a>(
+    tokens: &'t [Token<'a>],
+    cursor: usize,
+) -> Option<(&'t Token<'a>, &'t Token<'a>)> {
+    let is_paren = |token: &Token<'_>| matches!(token.value, TokenKind::LParen | TokenKind::RParen);
+    let at = tokens
+        .iter()
+        .position(|token| token.loc.0 == cursor && is_paren(token))
+        .or_else(|| {
+            tokens
+                .iter()
+                .position(|token| token.loc.1 == cursor && is_paren(token))
+        })?;
+
+    let mut depth = 0;
+    if tokens[at].value == TokenKind::LParen {
+        for token in &tokens[at..] {
+            match token.value {
+                TokenKind::LParen => depth += 1,
+                TokenKind::RParen => depth -= 1,
+                _ => continue,
+            }
+            if depth == 0 {
+                return Some((&tokens[at], token));
+            }
+        }
+    } else {
+        for token in tokens[..=at].iter().rev() {
+            match token.value {
+                TokenKind::RParen => depth += 1,
+                TokenKind::LParen => depth -= 1,
+                _ => continue,
+            }
+            if depth == 0 {
+                return Some((token, &tokens[at]));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        let highlight = |s: &str, cursor| SyntaxHighlighter.highlight(s, cursor);
+        let (n, o, i, k, p) = (NUMBER, OPERATOR, IDENT, KEYWORD, MATCHING_PAREN);
+        assert_eq!(
+            highlight("(1+ab) # c", 6),
+            vec![p, n, o, i, i, p, "", COMMENT, "", COMMENT]
+        );
+        assert_eq!(highlight("((2))", 1), vec!["", p, n, p, ""]);
+        assert_eq!(
+            highlight("prefix ~ (x) = 1é", 0),
+            vec![k, k, k, k, k, k, "", o, "", "", i, "", "", o, "", n, ERROR]
+        );
+    }
+}
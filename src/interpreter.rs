@@ -1,84 +1,727 @@
+use std::collections::HashMap;
 use std::fmt;
+use std::mem;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use super::lexer::{Annot, Loc};
-use super::parser::{print_annot, Ast, BinOp, UniOp};
+use super::complex::Complex;
+use super::lexer::Annot;
+use super::parser::{print_annot, Ast, AstNode, BinOp, BinOpKind, Fixity, Func, OpDecl, UniOp};
+use super::units::{self, Dimension, Quantity, UnitName};
 
 pub type Result<T> = std::result::Result<T, InterpreterError>;
 
-pub struct Interpreter;
+/// Result of an evaluation
+///
+/// Integer arithmetic is exact and stays within `EvalLimits::max_value`, while
+/// decimal literals and units make quantities, which are floating-point.
+/// Imaginary literals make complex numbers, which have no dimension.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Quantity(Quantity),
+    Complex(Complex),
+}
+
+impl Value {
+    /// The value as a quantity, integers being plain numbers, or `None` for a
+    /// complex number
+    fn to_quantity(&self) -> Option<Quantity> {
+        match self {
+            Value::Int(n) => Some(Quantity::new(*n as f64, Dimension::NONE)),
+            Value::Quantity(q) => Some(q.clone()),
+            Value::Complex(_) => None,
+        }
+    }
+
+    /// The value as a complex number, or `None` for a quantity with a
+    /// dimension
+    fn to_complex(&self) -> Option<Complex> {
+        match self {
+            Value::Complex(z) => Some(*z),
+            Value::Int(n) => Some(Complex::from(*n as f64)),
+            Value::Quantity(q) if q.dim == Dimension::NONE => Some(Complex::from(q.value)),
+            Value::Quantity(_) => None,
+        }
+    }
+
+    fn dim(&self) -> Dimension {
+        match self {
+            Value::Quantity(q) => q.dim,
+            Value::Int(_) | Value::Complex(_) => Dimension::NONE,
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Value::Int(n)
+    }
+}
+
+/// Parameters and body of a declared operator
+#[derive(Debug)]
+struct Definition {
+    params: Vec<String>,
+    body: Ast,
+    /// Operators declared before this one, which the body refers to
+    operators: Rc<Operators>,
+}
+
+/// Declared operators, by symbol
+#[derive(Debug, Clone, Default)]
+struct Operators {
+    unary: HashMap<String, Rc<Definition>>,
+    binary: HashMap<String, Rc<Definition>>,
+}
+
+/// Default of `EvalLimits::max_depth`
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Default of `EvalLimits::max_body_steps`
+pub const DEFAULT_MAX_BODY_STEPS: u64 = 1_000_000;
+
+/// Number of steps between two checks of the deadline
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// Budget of a single call to `Interpreter::eval`
+///
+/// By default only declared operators are limited: the steps spent in their
+/// bodies, since they can make an evaluation take exponential time, and their
+/// nesting, since every application of one evaluates its body on the call
+/// stack. Expressions without them take time linear in their size.
+///
+/// ```
+/// use std::time::Duration;
+/// use myparse::interpreter::{EvalLimits, Interpreter, Value};
+///
+/// let limits = EvalLimits::new()
+///     .max_steps(10_000)
+///     .timeout(Duration::from_millis(100));
+/// let mut interp = Interpreter::new().limits(limits);
+/// assert_eq!(interp.eval(&"1 + 2".parse().unwrap()), Ok(Value::Int(3)));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvalLimits {
+    max_steps: Option<u64>,
+    max_body_steps: u64,
+    max_depth: usize,
+    max_value: u64,
+    timeout: Option<Duration>,
+}
+
+impl Default for EvalLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EvalLimits {
+    pub fn new() -> Self {
+        EvalLimits {
+            max_steps: None,
+            max_body_steps: DEFAULT_MAX_BODY_STEPS,
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_value: i64::MAX as u64,
+            timeout: None,
+        }
+    }
+
+    /// Set the maximum number of nodes evaluated, counting the bodies of
+    /// declared operators once per application
+    pub fn max_steps(mut self, max_steps: u64) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    /// Set the maximum number of nodes evaluated in the bodies of declared
+    /// operators, over all their applications
+    pub fn max_body_steps(mut self, max_body_steps: u64) -> Self {
+        self.max_body_steps = max_body_steps;
+        self
+    }
+
+    /// Set the maximum nesting of applications of declared operators
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Set the maximum magnitude of integer literals and intermediate results
+    ///
+    /// Integers never exceed the range of `i64`, whatever the limit.
+    pub fn max_value(mut self, max_value: u64) -> Self {
+        self.max_value = max_value.min(i64::MAX as u64);
+        self
+    }
+
+    /// Set the wall-clock time an evaluation may take
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// Handle to cancel the evaluation of an `Interpreter` from another thread
+///
+/// Cancelling makes the evaluation in progress, or the next one if none is,
+/// fail with `Limit::Cancelled`.
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Take the pending cancellation request, if any
+    fn take(&self) -> bool {
+        self.0.load(Ordering::Relaxed) && self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Resources consumed by the current evaluation
+#[derive(Debug)]
+struct Budget {
+    steps: u64,
+    /// Steps spent in the bodies of declared operators
+    body_steps: u64,
+    depth: usize,
+    deadline: Option<Instant>,
+}
+
+/// Pending step of `Interpreter::eval`
+enum Frame<'a> {
+    /// Evaluate the node, pushing its value
+    Eval(&'a Ast),
+    /// Apply the operator of the node to the values of its operands
+    Apply(&'a Ast),
+}
+
+pub struct Interpreter {
+    operators: Rc<Operators>,
+    /// Operators seen by the expression being evaluated, those declared
+    /// before the operator whose body it is
+    scope: Rc<Operators>,
+    /// Arguments of the declared operator being evaluated
+    env: Vec<(String, Value)>,
+    /// Values of the previous evaluations, referred to as `_`, `ans` and `$n`
+    results: Vec<Value>,
+    limits: EvalLimits,
+    cancel: CancelHandle,
+    budget: Budget,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 impl Interpreter {
     pub fn new() -> Self {
-        Interpreter
+        Interpreter {
+            operators: Rc::default(),
+            scope: Rc::default(),
+            env: Vec::new(),
+            results: Vec::new(),
+            limits: EvalLimits::new(),
+            cancel: CancelHandle::default(),
+            budget: Budget {
+                steps: 0,
+                body_steps: 0,
+                depth: 0,
+                deadline: None,
+            },
+        }
+    }
+
+    /// Set the budget of every evaluation
+    pub fn limits(mut self, limits: EvalLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Handle to cancel the evaluations of this interpreter
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel.clone()
+    }
+
+    /// Define the operator of `decl`, replacing any previous definition
+    ///
+    /// Like the parser, the body refers to the operators declared so far, so
+    /// redeclaring an operator in terms of itself uses its previous
+    /// definition.
+    pub fn define(&mut self, decl: &OpDecl) {
+        let def = Rc::new(Definition {
+            params: decl.params.clone(),
+            body: decl.body.clone(),
+            operators: self.operators.clone(),
+        });
+        let symbol = decl.symbol.value.to_string();
+        let operators = Rc::make_mut(&mut self.operators);
+        match decl.fixity {
+            Fixity::Infix(_) => operators.binary.insert(symbol, def),
+            Fixity::Prefix | Fixity::Postfix => operators.unary.insert(symbol, def),
+        };
+    }
+
+    /// Store `value` as the latest result, returning its number `n` in `$n`
+    pub fn record(&mut self, value: Value) -> usize {
+        self.results.push(value);
+        self.results.len()
     }
 
-    pub fn eval(&mut self, expr: &Ast) -> Result<i64> {
+    /// Values recorded so far, the first one being `$1`
+    pub fn results(&self) -> &[Value] {
+        &self.results
+    }
+
+    /// Symbols of the defined operators
+    pub fn operators(&self) -> impl Iterator<Item = &str> {
+        self.operators
+            .unary
+            .keys()
+            .chain(self.operators.binary.keys())
+            .map(String::as_str)
+    }
+
+    /// Value of the variable `name` outside of operator declarations, a
+    /// previous result or a unit
+    fn lookup(&self, name: &str) -> std::result::Result<Value, InterpreterErrorKind> {
+        let index = match name {
+            "_" | "ans" => self.results.len().checked_sub(1),
+            _ => match name.strip_prefix('$') {
+                Some(n) => n.parse::<usize>().ok().and_then(|n| n.checked_sub(1)),
+                None => return unit(name),
+            },
+        };
+        index
+            .and_then(|i| self.results.get(i).cloned())
+            .ok_or_else(|| InterpreterErrorKind::NoSuchResult(name.to_string()))
+    }
+
+    /// Evaluate `expr` within the limits of the interpreter
+    ///
+    /// The tree is walked with an explicit stack instead of recursion, so the
+    /// depth of `expr` is only limited by memory.
+    pub fn eval(&mut self, expr: &Ast) -> Result<Value> {
+        self.budget = Budget {
+            steps: 0,
+            body_steps: 0,
+            depth: 0,
+            deadline: self.limits.timeout.map(|timeout| Instant::now() + timeout),
+        };
+        self.scope = self.operators.clone();
+        self.eval_expr(expr)
+    }
+
+    fn eval_expr(&mut self, expr: &Ast) -> Result<Value> {
         use super::parser::AstNode::*;
-        match expr.value {
-            Num(n) => Ok(n as i64),
-            UniOp { ref op, ref e } => {
-                let e = self.eval(e)?;
-                self.eval_uniop(op, e)
-                    .map_err(|err| InterpreterError::new(err, expr.loc.clone()))
-            }
-            BinOp {
-                ref op,
-                ref l,
-                ref r,
-            } => {
-                let l = self.eval(l)?;
-                let r = self.eval(r)?;
-                self.eval_binop(op, l, r)
-                    .map_err(|err| InterpreterError::new(err, expr.loc.clone()))
+        let mut work = vec![Frame::Eval(expr)];
+        let mut values = Vec::new();
+        while let Some(frame) = work.pop() {
+            match frame {
+                Frame::Eval(expr) => {
+                    let located = |err| InterpreterError::new(err, expr.loc.clone());
+                    self.spend_step().map_err(located)?;
+                    match expr.value {
+                        Num(n) => {
+                            let n =
+                                check_value(n.into(), self.limits.max_value).map_err(located)?;
+                            values.push(Value::Int(n));
+                        }
+                        Decimal(d) => {
+                            values
+                                .push(Value::Quantity(Quantity::new(d.to_f64(), Dimension::NONE)));
+                        }
+                        Imaginary(d) => values.push(Value::Complex(Complex::new(0.0, d.to_f64()))),
+                        Var(ref name) => match self.env.iter().find(|(param, _)| param == name) {
+                            Some((_, value)) => values.push(value.clone()),
+                            // the body of a declared operator sees only its
+                            // parameters and the units
+                            None if self.budget.depth > 0 => {
+                                values.push(unit(name).map_err(located)?)
+                            }
+                            None => values.push(self.lookup(name).map_err(located)?),
+                        },
+                        UniOp { ref e, .. } => {
+                            work.push(Frame::Apply(expr));
+                            work.push(Frame::Eval(e));
+                        }
+                        BinOp { ref l, ref r, .. } => {
+                            work.push(Frame::Apply(expr));
+                            work.push(Frame::Eval(r));
+                            work.push(Frame::Eval(l));
+                        }
+                    }
+                }
+                Frame::Apply(expr) => {
+                    let n = match expr.value {
+                        UniOp { ref op, .. } => {
+                            let e = values.pop().unwrap();
+                            self.eval_uniop(op, e)
+                        }
+                        BinOp { ref op, ref r, .. } => {
+                            let rv = values.pop().unwrap();
+                            let lv = values.pop().unwrap();
+                            match op.value {
+                                BinOpKind::Convert => convert(lv, rv, r),
+                                _ => self.eval_binop(op, lv, rv),
+                            }
+                        }
+                        Num(_) | Decimal(_) | Imaginary(_) | Var(_) => unreachable!(),
+                    };
+                    let n = n.map_err(|err| InterpreterError::new(err, expr.loc.clone()))?;
+                    values.push(n);
+                }
             }
         }
+        Ok(values.pop().unwrap())
     }
 
-    fn eval_uniop(&mut self, op: &UniOp, n: i64) -> std::result::Result<i64, InterpreterErrorKind> {
+    /// Account for the evaluation of one node
+    fn spend_step(&mut self) -> std::result::Result<(), InterpreterErrorKind> {
+        let budget = &mut self.budget;
+        budget.steps += 1;
+        if budget.depth > 0 {
+            budget.body_steps += 1;
+        }
+        let exceeded = if self.cancel.take() {
+            Some(Limit::Cancelled)
+        } else if self.limits.max_steps.is_some_and(|max| budget.steps > max) {
+            self.limits.max_steps.map(Limit::Steps)
+        } else if budget.body_steps > self.limits.max_body_steps {
+            Some(Limit::BodySteps(self.limits.max_body_steps))
+        } else if budget.steps % CLOCK_INTERVAL == 1
+            && budget
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            self.limits.timeout.map(Limit::Timeout)
+        } else {
+            None
+        };
+        match exceeded {
+            Some(limit) => Err(InterpreterErrorKind::LimitExceeded(limit)),
+            None => Ok(()),
+        }
+    }
+
+    fn eval_uniop(
+        &mut self,
+        op: &UniOp,
+        value: Value,
+    ) -> std::result::Result<Value, InterpreterErrorKind> {
         use super::parser::UniOpKind::*;
-        match op.value {
-            Plus => Ok(n),
-            Minus => Ok(-n),
+        let max = self.limits.max_value;
+        match (&op.value, value) {
+            (Custom(symbol), value) => match self.scope.unary.get(symbol).cloned() {
+                Some(def) => self.call(&def, vec![value]),
+                None => Err(InterpreterErrorKind::UndefinedOperator(symbol.clone())),
+            },
+            (Func(func), value) => eval_func(*func, value, max),
+            (Plus, Value::Int(n)) => check_value(n.into(), max).map(Value::Int),
+            (Minus, Value::Int(n)) => check_value(-i128::from(n), max).map(Value::Int),
+            (Plus, value @ Value::Quantity(_)) | (Plus, value @ Value::Complex(_)) => Ok(value),
+            (Minus, Value::Quantity(q)) => Ok(Value::Quantity(Quantity {
+                value: -q.value,
+                ..q
+            })),
+            (Minus, Value::Complex(z)) => Ok(Value::Complex(-z)),
         }
     }
 
     fn eval_binop(
+        &mut self,
+        op: &BinOp,
+        l: Value,
+        r: Value,
+    ) -> std::result::Result<Value, InterpreterErrorKind> {
+        use super::parser::BinOpKind::*;
+        let (l, r) = match (&op.value, l, r) {
+            (Custom(symbol), l, r) => {
+                return match self.scope.binary.get(symbol).cloned() {
+                    Some(def) => self.call(&def, vec![l, r]),
+                    None => Err(InterpreterErrorKind::UndefinedOperator(symbol.clone())),
+                }
+            }
+            (_, Value::Int(l), Value::Int(r)) => return self.eval_int_binop(op, l, r),
+            (_, l @ Value::Complex(_), r) | (_, l, r @ Value::Complex(_)) => {
+                return match (l.to_complex(), r.to_complex()) {
+                    (Some(l), Some(r)) => eval_complex_binop(op, l, r),
+                    _ => Err(InterpreterErrorKind::DimensionMismatch(l.dim(), r.dim())),
+                }
+            }
+            (_, l, r) => (l.to_quantity().unwrap(), r.to_quantity().unwrap()),
+        };
+        let (value, dim) = match op.value {
+            Add | Sub if l.dim != r.dim => {
+                return Err(InterpreterErrorKind::DimensionMismatch(l.dim, r.dim))
+            }
+            Add => (l.value + r.value, Some(l.dim)),
+            Sub => (l.value - r.value, Some(l.dim)),
+            Mul => (l.value * r.value, l.dim.checked_mul(r.dim)),
+            Div if r.value == 0.0 => return Err(InterpreterErrorKind::DivisionByZero),
+            Div => (l.value / r.value, l.dim.checked_div(r.dim)),
+            Convert | Custom(_) => unreachable!(),
+        };
+        let dim = dim.ok_or(InterpreterErrorKind::DimensionOverflow)?;
+        Ok(Value::Quantity(Quantity::new(value, dim)))
+    }
+
+    fn eval_int_binop(
         &mut self,
         op: &BinOp,
         l: i64,
         r: i64,
-    ) -> std::result::Result<i64, InterpreterErrorKind> {
+    ) -> std::result::Result<Value, InterpreterErrorKind> {
         use super::parser::BinOpKind::*;
-        match op.value {
-            Add => Ok(l + r),
-            Sub => Ok(l - r),
-            Mul => Ok(l * r),
+        // the product of two `i64` always fits in an `i128`
+        let (wl, wr) = (i128::from(l), i128::from(r));
+        let max = self.limits.max_value;
+        let n = match op.value {
+            Add => check_value(wl + wr, max),
+            Sub => check_value(wl - wr, max),
+            Mul => check_value(wl * wr, max),
             Div => {
                 if r == 0 {
                     Err(InterpreterErrorKind::DivisionByZero)
                 } else {
-                    Ok(l / r)
+                    check_value(wl / wr, max)
                 }
             }
+            Convert | Custom(_) => unreachable!(),
+        };
+        n.map(Value::Int)
+    }
+
+    /// Evaluate the body of `def` with only its parameters in scope
+    ///
+    /// The locations in the body belong to the declaration, so an error is
+    /// reported at the application of the operator instead.
+    fn call(
+        &mut self,
+        def: &Definition,
+        args: Vec<Value>,
+    ) -> std::result::Result<Value, InterpreterErrorKind> {
+        if self.budget.depth >= self.limits.max_depth {
+            return Err(InterpreterErrorKind::LimitExceeded(Limit::Depth(
+                self.limits.max_depth,
+            )));
+        }
+        let env = def.params.iter().cloned().zip(args).collect();
+        let saved = mem::replace(&mut self.env, env);
+        let saved_scope = mem::replace(&mut self.scope, def.operators.clone());
+        self.budget.depth += 1;
+        let ret = self.eval_expr(&def.body);
+        self.budget.depth -= 1;
+        self.env = saved;
+        self.scope = saved_scope;
+        ret.map_err(|err| err.value)
+    }
+}
+
+/// Apply `op` to complex numbers
+fn eval_complex_binop(
+    op: &BinOp,
+    l: Complex,
+    r: Complex,
+) -> std::result::Result<Value, InterpreterErrorKind> {
+    use super::parser::BinOpKind::*;
+    let z = match op.value {
+        Add => l + r,
+        Sub => l - r,
+        Mul => l * r,
+        Div if r == Complex::ZERO => return Err(InterpreterErrorKind::DivisionByZero),
+        Div => l / r,
+        Convert | Custom(_) => unreachable!(),
+    };
+    Ok(Value::Complex(z))
+}
+
+/// Apply the built-in function `func` to `value`
+///
+/// Real numbers are their own real part and conjugate, and keep their unit
+/// through `re`, `abs` and `conj`.
+fn eval_func(
+    func: Func,
+    value: Value,
+    max: u64,
+) -> std::result::Result<Value, InterpreterErrorKind> {
+    let real = |x| Ok(Value::Quantity(Quantity::new(x, Dimension::NONE)));
+    match (func, value) {
+        (Func::Re, Value::Complex(z)) => real(z.re),
+        (Func::Im, Value::Complex(z)) => real(z.im),
+        (Func::Abs, Value::Complex(z)) => real(z.abs()),
+        (Func::Arg, Value::Complex(z)) => real(z.arg()),
+        (Func::Conj, Value::Complex(z)) => Ok(Value::Complex(z.conj())),
+        (Func::Re, value) | (Func::Conj, value) => Ok(value),
+        (Func::Im, Value::Int(_)) => Ok(Value::Int(0)),
+        (Func::Im, value) => Ok(Value::Quantity(Quantity::new(0.0, value.dim()))),
+        (Func::Abs, Value::Int(n)) => check_value(i128::from(n).abs(), max).map(Value::Int),
+        (Func::Abs, Value::Quantity(q)) => Ok(Value::Quantity(Quantity {
+            value: q.value.abs(),
+            ..q
+        })),
+        (Func::Arg, value) => real(Complex::from(value.to_quantity().unwrap().value).arg()),
+    }
+}
+
+/// The unit `name` as a quantity
+fn unit(name: &str) -> std::result::Result<Value, InterpreterErrorKind> {
+    match units::lookup(name) {
+        Some(unit) => Ok(Value::Quantity(Quantity::new(unit.factor, unit.dim))),
+        None => Err(InterpreterErrorKind::UnboundVariable(name.to_string())),
+    }
+}
+
+/// Convert `value` to the unit written as `unit`, whose value is `factor`
+fn convert(
+    value: Value,
+    factor: Value,
+    unit: &Ast,
+) -> std::result::Result<Value, InterpreterErrorKind> {
+    let name = unit_name(unit).ok_or(InterpreterErrorKind::NotAUnit)?;
+    let (q, factor) = match (value.to_quantity(), factor.to_quantity()) {
+        (Some(q), Some(factor)) => (q, factor),
+        // complex numbers have no dimension, so no unit fits them
+        _ => {
+            return Err(InterpreterErrorKind::DimensionMismatch(
+                value.dim(),
+                factor.dim(),
+            ))
+        }
+    };
+    if q.dim != factor.dim {
+        return Err(InterpreterErrorKind::DimensionMismatch(q.dim, factor.dim));
+    }
+    if factor.value == 0.0 {
+        return Err(InterpreterErrorKind::DivisionByZero);
+    }
+    Ok(Value::Quantity(Quantity {
+        unit: Some(UnitName {
+            name,
+            factor: factor.value,
+        }),
+        ..q
+    }))
+}
+
+/// Name of the unit written as `ast`, a product or quotient of names such as
+/// `kg*m/s`, or `None` for any other expression
+fn unit_name(ast: &Ast) -> Option<String> {
+    enum Part<'a> {
+        Unit(&'a Ast),
+        Text(&'static str),
+    }
+    let mut name = String::new();
+    let mut parts = vec![Part::Unit(ast)];
+    while let Some(part) = parts.pop() {
+        let ast = match part {
+            Part::Unit(ast) => ast,
+            Part::Text(text) => {
+                name.push_str(text);
+                continue;
+            }
+        };
+        match ast.value {
+            AstNode::Var(ref unit) => name.push_str(unit),
+            AstNode::BinOp {
+                ref op,
+                ref l,
+                ref r,
+            } => {
+                let sep = match op.value {
+                    BinOpKind::Mul => "*",
+                    BinOpKind::Div => "/",
+                    _ => return None,
+                };
+                // the right operand is grouped, as the operators are left
+                // associative
+                if let AstNode::BinOp { .. } = r.value {
+                    parts.push(Part::Text(")"));
+                    parts.push(Part::Unit(r));
+                    parts.push(Part::Text("("));
+                } else {
+                    parts.push(Part::Unit(r));
+                }
+                parts.push(Part::Text(sep));
+                parts.push(Part::Unit(l));
+            }
+            _ => return None,
+        }
+    }
+    Some(name)
+}
+
+/// Convert `n` to an `i64` if its magnitude is at most `max`
+fn check_value(n: i128, max: u64) -> std::result::Result<i64, InterpreterErrorKind> {
+    if n.unsigned_abs() <= u128::from(max) {
+        Ok(n as i64)
+    } else {
+        Err(InterpreterErrorKind::LimitExceeded(Limit::Value(max)))
+    }
+}
+
+/// Limit of `EvalLimits` exceeded by an evaluation
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Limit {
+    Steps(u64),
+    /// Steps spent in the bodies of declared operators
+    BodySteps(u64),
+    Depth(usize),
+    Value(u64),
+    Timeout(Duration),
+    /// Evaluation cancelled through a `CancelHandle`
+    Cancelled,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Limit::*;
+        match self {
+            Steps(max) => write!(f, "evaluation takes more than {} steps", max),
+            BodySteps(max) => write!(f, "declared operators take more than {} steps", max),
+            Depth(max) => write!(f, "operators applied more than {} levels deep", max),
+            Value(max) => write!(f, "value larger than {} in magnitude", max),
+            Timeout(timeout) => write!(f, "evaluation takes longer than {:?}", timeout),
+            Cancelled => write!(f, "evaluation cancelled"),
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum InterpreterErrorKind {
     DivisionByZero,
+    /// A name which is not a parameter of the enclosing operator declaration
+    UnboundVariable(String),
+    /// A declared operator without definition
+    UndefinedOperator(String),
+    /// A reference to a previous result, e.g. `$3`, beyond the results so far
+    NoSuchResult(String),
+    /// The budget of the evaluation ran out at this node
+    LimitExceeded(Limit),
+    /// Operands of `+`, `-` or `in` with different dimensions, or a complex
+    /// number with a quantity which has one
+    DimensionMismatch(Dimension, Dimension),
+    /// Right operand of `in` which is not a product or quotient of units
+    NotAUnit,
+    /// Product or quotient with an exponent of a base unit out of the range
+    /// of `i8`, e.g. `m^128`
+    DimensionOverflow,
 }
 
 pub type InterpreterError = Annot<InterpreterErrorKind>;
 
 impl InterpreterError {
     pub fn show_diagnostic(&self, input: &str) {
-        use self::InterpreterErrorKind::*;
-        let (err, loc): (&std::error::Error, &Loc) = match self.value {
-            DivisionByZero => (self, &self.loc),
-        };
-        eprintln!("{}", err);
-        print_annot(input, loc);
+        eprintln!("{}", self);
+        print_annot(input, &self.loc);
     }
 }
 
@@ -86,9 +729,315 @@ impl std::error::Error for InterpreterError {}
 
 impl fmt::Display for InterpreterError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "division by zero: the right hand expression of the division evaluates to zero"
-        )
+        use self::InterpreterErrorKind::*;
+        match self.value {
+            DivisionByZero => write!(
+                f,
+                "division by zero: the right hand expression of the division evaluates to zero"
+            ),
+            UnboundVariable(ref name) => write!(f, "{}: '{}' is not defined", self.loc, name),
+            UndefinedOperator(ref symbol) => {
+                write!(f, "{}: operator '{}' has no definition", self.loc, symbol)
+            }
+            NoSuchResult(ref name) => write!(f, "{}: no such result '{}'", self.loc, name),
+            LimitExceeded(ref limit) => write!(f, "{}: {}", self.loc, limit),
+            DimensionMismatch(l, r) => {
+                write!(f, "{}: mismatched dimensions {} and {}", self.loc, l, r)
+            }
+            NotAUnit => write!(f, "{}: expected a unit after 'in'", self.loc),
+            DimensionOverflow => write!(f, "{}: dimension exponent overflow", self.loc),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::{Lexer, Loc};
+    use crate::parser::{parse_stmt_with, parse_tokens_with, ParseConfig, Stmt};
+
+    #[test]
+    fn test_declared_operators() {
+        let mut config = ParseConfig::new();
+        let mut interp = Interpreter::new();
+        let mut run = |s| match parse_stmt_with(Lexer::new(s), &config, None).unwrap() {
+            Stmt::OpDecl(decl) => {
+                config.declare(&decl).unwrap();
+                interp.define(&decl);
+                None
+            }
+            Stmt::Expr(ast) => Some(interp.eval(&ast)),
+        };
+        assert_eq!(run("infixl 6 <+> (a, b) = a + 2*b"), None);
+        assert_eq!(run("prefix ~~ (x) = -x"), None);
+        assert_eq!(run("1 <+> 2 <+> 3"), Some(Ok(Value::Int(11))));
+        assert_eq!(run("~~(1 <+> 1) * 2"), Some(Ok(Value::Int(-6))));
+        assert_eq!(run("infixl 6 <?> (a, b) = c"), None);
+        assert_eq!(
+            run("1 + (2 <?> 3)"),
+            Some(Err(InterpreterError::new(
+                InterpreterErrorKind::UnboundVariable("c".to_string()),
+                Loc(5, 12)
+            )))
+        );
+    }
+
+    #[test]
+    fn test_results() {
+        let mut interp = Interpreter::new();
+        let mut eval = |s: &str| {
+            let value = interp.eval(&s.parse().unwrap())?;
+            interp.record(value.clone());
+            Ok(value)
+        };
+        let no_such_result = |name: &str, loc| {
+            Err(InterpreterError::new(
+                InterpreterErrorKind::NoSuchResult(name.to_string()),
+                loc,
+            ))
+        };
+        assert_eq!(eval("1 + _"), no_such_result("_", Loc(4, 5)));
+        assert_eq!(eval("2 * 3"), Ok(Value::Int(6)));
+        assert_eq!(eval("ans + 1"), Ok(Value::Int(7)));
+        assert_eq!(eval("$1 * _"), Ok(Value::Int(42)));
+        assert_eq!(eval("$4 - $0"), no_such_result("$4", Loc(0, 2)));
+        assert_eq!(
+            eval("x"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::UnboundVariable("x".to_string()),
+                Loc(0, 1)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_eval_limits() {
+        let exceeded = |limit, loc| {
+            Err(InterpreterError::new(
+                InterpreterErrorKind::LimitExceeded(limit),
+                loc,
+            ))
+        };
+        let eval = |interp: &mut Interpreter, s: &str| interp.eval(&s.parse().unwrap());
+
+        let mut interp = Interpreter::new().limits(EvalLimits::new().max_steps(5));
+        assert_eq!(eval(&mut interp, "1 + 2 * 3"), Ok(Value::Int(7)));
+        assert_eq!(
+            eval(&mut interp, "1 + 2 * (3 - 4)"),
+            exceeded(Limit::Steps(5), Loc(9, 10))
+        );
+
+        let mut interp = Interpreter::new().limits(EvalLimits::new().max_value(100));
+        assert_eq!(eval(&mut interp, "99 + 1"), Ok(Value::Int(100)));
+        assert_eq!(
+            eval(&mut interp, "1 + 10 * (9 + 2)"),
+            exceeded(Limit::Value(100), Loc(4, 15))
+        );
+        let mut interp = Interpreter::new();
+        assert_eq!(
+            eval(&mut interp, "9223372036854775807 + 1"),
+            exceeded(Limit::Value(i64::MAX as u64), Loc(0, 23))
+        );
+
+        let mut interp = Interpreter::new().limits(EvalLimits::new().timeout(Duration::ZERO));
+        assert_eq!(
+            eval(&mut interp, "1 + 2"),
+            exceeded(Limit::Timeout(Duration::ZERO), Loc(0, 5))
+        );
+
+        let mut interp = Interpreter::new();
+        interp.cancel_handle().cancel();
+        assert_eq!(
+            eval(&mut interp, "1 + 2"),
+            exceeded(Limit::Cancelled, Loc(0, 5))
+        );
+        assert_eq!(eval(&mut interp, "1 + 2"), Ok(Value::Int(3)));
+
+        // only the bodies of declared operators are charged
+        let mut interp = Interpreter::new().limits(EvalLimits::new().max_body_steps(10));
+        let decl = "infixl 6 <+> (a, b) = a + b + 1";
+        let mut config = ParseConfig::new();
+        match parse_stmt_with(Lexer::new(decl), &config, None).unwrap() {
+            Stmt::OpDecl(decl) => {
+                config.declare(&decl).unwrap();
+                interp.define(&decl);
+            }
+            Stmt::Expr(_) => unreachable!(),
+        }
+        let mut eval =
+            |s: &str| interp.eval(&parse_tokens_with(Lexer::new(s), &config, None).unwrap());
+        assert_eq!(eval(&format!("{}1", "1+".repeat(99))), Ok(Value::Int(100)));
+        assert_eq!(eval("1 <+> 2 <+> 3"), Ok(Value::Int(8)));
+        assert_eq!(
+            eval("1 <+> 2 <+> 3 <+> 4"),
+            exceeded(Limit::BodySteps(10), Loc(0, 19))
+        );
+    }
+
+    #[test]
+    fn test_eval_recursion_limit() {
+        let mut config = ParseConfig::new();
+        let mut interp = Interpreter::new().limits(EvalLimits::new().max_depth(3));
+        let mut run = |s: &str| match parse_stmt_with(Lexer::new(s), &config, None).unwrap() {
+            Stmt::OpDecl(decl) => {
+                config.declare(&decl).unwrap();
+                interp.define(&decl);
+                None
+            }
+            Stmt::Expr(ast) => Some(interp.eval(&ast)),
+        };
+        // every body refers to the previous declaration of `<+>`
+        assert_eq!(run("infixl 6 <+> (a, b) = a + b"), None);
+        assert_eq!(run("infixl 6 <+> (a, b) = a <+> b <+> 1"), None);
+        assert_eq!(run("1 + (1 <+> 2)"), Some(Ok(Value::Int(5))));
+        assert_eq!(run("infixl 6 <+> (a, b) = a <+> b"), None);
+        assert_eq!(run("infixl 6 <+> (a, b) = a <+> b"), None);
+        assert_eq!(
+            run("1 + (1 <+> 2)"),
+            Some(Err(InterpreterError::new(
+                InterpreterErrorKind::LimitExceeded(Limit::Depth(3)),
+                Loc(5, 12)
+            )))
+        );
+
+        // every operator applies the previous one thrice, which the default
+        // limit on the steps spent in bodies stops
+        let mut interp = Interpreter::new();
+        let mut run = |s: &str| match parse_stmt_with(Lexer::new(s), &config, None).unwrap() {
+            Stmt::OpDecl(decl) => {
+                config.declare(&decl).unwrap();
+                interp.define(&decl);
+                None
+            }
+            Stmt::Expr(ast) => Some(interp.eval(&ast)),
+        };
+        let mut op = "+".to_string();
+        for n in 1..=20 {
+            let next = format!("<{}>", "*".repeat(n));
+            run(&format!(
+                "infixl 6 {1} (a, b) = (a {0} b) - (a {0} b) + (a {0} b)",
+                op, next
+            ));
+            op = next;
+        }
+        assert_eq!(
+            run(&format!("1 {} 2", op)),
+            Some(Err(InterpreterError::new(
+                InterpreterErrorKind::LimitExceeded(Limit::BodySteps(DEFAULT_MAX_BODY_STEPS)),
+                Loc(0, 26)
+            )))
+        );
+    }
+
+    #[test]
+    fn test_units() {
+        let mut interp = Interpreter::new();
+        let mut eval = |s: &str| {
+            let value = interp.eval(&s.parse().unwrap())?;
+            interp.record(value.clone());
+            Ok(value)
+        };
+        let quantity = |value, dim| Ok(Value::Quantity(Quantity::new(value, Dimension(dim))));
+        assert_eq!(eval("3 m * 2"), quantity(6.0, [1, 0, 0, 0, 0, 0, 0]));
+        assert_eq!(
+            eval("1.5 km / 1 min"),
+            quantity(25.0, [1, 0, -1, 0, 0, 0, 0])
+        );
+        assert_eq!(eval("_ / (5 m/s)"), quantity(5.0, [0; 7]));
+        assert_eq!(
+            eval("$1 + 0.5"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::DimensionMismatch(
+                    Dimension([1, 0, 0, 0, 0, 0, 0]),
+                    Dimension::NONE
+                ),
+                Loc(0, 8)
+            ))
+        );
+        match eval("-$2 in km/h") {
+            Ok(Value::Quantity(Quantity {
+                value,
+                unit: Some(unit),
+                ..
+            })) => {
+                assert_eq!((value, &unit.name[..]), (-25.0, "km/h"))
+            }
+            ret => panic!("{:?}", ret),
+        }
+        assert_eq!(
+            eval("1 m in 2 m"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::NotAUnit,
+                Loc(0, 10)
+            ))
+        );
+        assert_eq!(
+            eval("1 m / (0.0 s)"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::DivisionByZero,
+                Loc(0, 12)
+            ))
+        );
+        assert_eq!(
+            eval(&format!("1 m{}", " * m".repeat(130))),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::DimensionOverflow,
+                Loc(0, 511)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_complex() {
+        let eval = |s: &str| Interpreter::new().eval(&s.parse().unwrap());
+        let complex = |re, im| Ok(Value::Complex(Complex::new(re, im)));
+        let real = |x| Ok(Value::Quantity(Quantity::new(x, Dimension::NONE)));
+        assert_eq!(eval("(1 + 2i) * (3 - 1j)"), complex(5.0, 5.0));
+        assert_eq!(eval("-(5 + 5i) / (1 + 2i)"), complex(-3.0, 1.0));
+        assert_eq!(eval("conj 0.5i + 1"), complex(1.0, -0.5));
+        assert_eq!(eval("abs(3 + 4i)"), real(5.0));
+        assert_eq!(eval("re(2.5j) + im(2.5j)"), real(2.5));
+        assert_eq!(eval("arg(-2)"), real(std::f64::consts::PI));
+        assert_eq!(eval("abs -3 - im 3"), Ok(Value::Int(3)));
+        assert_eq!(
+            eval("1 / (2i - 2j)"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::DivisionByZero,
+                Loc(0, 12)
+            ))
+        );
+        assert_eq!(
+            eval("2i * 3 m"),
+            Err(InterpreterError::new(
+                InterpreterErrorKind::DimensionMismatch(
+                    Dimension::NONE,
+                    Dimension([1, 0, 0, 0, 0, 0, 0])
+                ),
+                Loc(0, 8)
+            ))
+        );
+    }
+
+    #[test]
+    fn test_eval_deep() {
+        // a left-leaning tree as deep as the number of terms
+        let src = vec!["1"; 100_000].join(" + ");
+        let ast = src.parse::<Ast>().unwrap();
+        assert_eq!(Interpreter::new().eval(&ast), Ok(Value::Int(100_000)));
+        drop(ast);
+
+        // the body is copied into the definition
+        let src = format!("infixl 6 <+> (a, b) = {} + b", src);
+        let mut config = ParseConfig::new();
+        let mut interp = Interpreter::new();
+        match parse_stmt_with(Lexer::new(&src), &config, None).unwrap() {
+            Stmt::OpDecl(decl) => {
+                config.declare(&decl).unwrap();
+                interp.define(&decl);
+            }
+            Stmt::Expr(_) => unreachable!(),
+        }
+        let ast = parse_tokens_with(Lexer::new("2 <+> 3"), &config, None).unwrap();
+        assert_eq!(interp.eval(&ast), Ok(Value::Int(100_003)));
     }
 }
@@ -0,0 +1,420 @@
+//! Minimal JSON values, reader and writer used for exporting syntax trees
+
+use std::fmt;
+use std::mem;
+
+use super::lexer::{Annot, Loc};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    /// Numbers are kept as written so that integers are never rounded
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            Json::Number(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
+impl From<u64> for Json {
+    fn from(n: u64) -> Self {
+        Json::Number(n.to_string())
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Json::Number(n.to_string())
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+/// A piece of output still to be written by `Json`'s `Display`
+enum Piece<'a> {
+    Value(&'a Json),
+    Key(&'a str),
+    Text(&'static str),
+}
+
+impl fmt::Display for Json {
+    /// Written with an explicit stack so that values of any depth can be
+    /// printed
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut stack = vec![Piece::Value(self)];
+        while let Some(piece) = stack.pop() {
+            match piece {
+                Piece::Value(Json::Null) => write!(f, "null")?,
+                Piece::Value(Json::Bool(b)) => write!(f, "{}", b)?,
+                Piece::Value(Json::Number(n)) => write!(f, "{}", n)?,
+                Piece::Value(Json::String(s)) => write_str(f, s)?,
+                Piece::Value(Json::Array(values)) => {
+                    write!(f, "[")?;
+                    stack.push(Piece::Text("]"));
+                    for (i, v) in values.iter().enumerate().rev() {
+                        stack.push(Piece::Value(v));
+                        if i > 0 {
+                            stack.push(Piece::Text(","));
+                        }
+                    }
+                }
+                Piece::Value(Json::Object(fields)) => {
+                    write!(f, "{{")?;
+                    stack.push(Piece::Text("}"));
+                    for (i, (k, v)) in fields.iter().enumerate().rev() {
+                        stack.push(Piece::Value(v));
+                        stack.push(Piece::Text(":"));
+                        stack.push(Piece::Key(k));
+                        if i > 0 {
+                            stack.push(Piece::Text(","));
+                        }
+                    }
+                }
+                Piece::Key(k) => write_str(f, k)?,
+                Piece::Text(text) => f.write_str(text)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Json {
+    /// Moves the nested values out before they are dropped, so that
+    /// dropping a deep value does not recurse
+    fn drop(&mut self) {
+        fn take_children(value: &mut Json, stack: &mut Vec<Json>) {
+            match value {
+                Json::Array(values) => stack.append(values),
+                Json::Object(fields) => stack.extend(fields.drain(..).map(|(_, v)| v)),
+                _ => {}
+            }
+        }
+        let mut stack = Vec::new();
+        take_children(self, &mut stack);
+        while let Some(mut value) = stack.pop() {
+            take_children(&mut value, &mut stack);
+        }
+    }
+}
+
+fn write_str(f: &mut fmt::Formatter, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum JsonErrorKind {
+    InvalidChar(char),
+    InvalidNumber,
+    InvalidEscape,
+    Eof,
+}
+
+pub type JsonError = Annot<JsonErrorKind>;
+
+impl std::error::Error for JsonError {}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::JsonErrorKind::*;
+        let loc = &self.loc;
+        match self.value {
+            InvalidChar(c) => write!(f, "{}: invalid char '{}' in JSON", loc, c),
+            InvalidNumber => write!(f, "{}: invalid JSON number", loc),
+            InvalidEscape => write!(f, "{}: invalid escape sequence in JSON string", loc),
+            Eof => write!(f, "End of file"),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, JsonError>;
+
+/// Parse a single JSON document
+pub fn parse(input: &str) -> Result<Json> {
+    let mut reader = Reader { input, pos: 0 };
+    let value = reader.value()?;
+    reader.skip_spaces();
+    match reader.peek() {
+        Some(c) => Err(reader.invalid_char(c)),
+        None => Ok(value),
+    }
+}
+
+struct Reader<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+/// An array or object whose values are being read
+enum Open {
+    Array(Vec<Json>),
+    /// The fields read so far and the key of the next value
+    Object(Vec<(String, Json)>, String),
+}
+
+impl<'a> Reader<'a> {
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn bump(&mut self) -> Result<char> {
+        let c = self
+            .peek()
+            .ok_or_else(|| JsonError::new(JsonErrorKind::Eof, Loc(self.pos, self.pos)))?;
+        self.pos += c.len_utf8();
+        Ok(c)
+    }
+
+    fn invalid_char(&self, c: char) -> JsonError {
+        JsonError::new(
+            JsonErrorKind::InvalidChar(c),
+            Loc(self.pos, self.pos + c.len_utf8()),
+        )
+    }
+
+    fn expect(&mut self, expected: char) -> Result<()> {
+        self.skip_spaces();
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(c) => Err(self.invalid_char(c)),
+            None => Err(JsonError::new(JsonErrorKind::Eof, Loc(self.pos, self.pos))),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while let Some(' ') | Some('\n') | Some('\r') | Some('\t') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    /// Read a value, keeping the arrays and objects being read on an
+    /// explicit stack so that values of any depth can be read
+    fn value(&mut self) -> Result<Json> {
+        let mut open = Vec::new();
+        loop {
+            self.skip_spaces();
+            let mut value = match self.peek() {
+                Some('[') => {
+                    self.pos += 1;
+                    self.skip_spaces();
+                    if self.peek() == Some(']') {
+                        self.pos += 1;
+                        Json::Array(Vec::new())
+                    } else {
+                        open.push(Open::Array(Vec::new()));
+                        continue;
+                    }
+                }
+                Some('{') => {
+                    self.pos += 1;
+                    self.skip_spaces();
+                    if self.peek() == Some('}') {
+                        self.pos += 1;
+                        Json::Object(Vec::new())
+                    } else {
+                        open.push(Open::Object(Vec::new(), self.key()?));
+                        continue;
+                    }
+                }
+                Some('"') => Json::String(self.string()?),
+                Some('-') | Some('0'..='9') => self.number()?,
+                Some(c) if c.is_ascii_alphabetic() => self.keyword()?,
+                Some(c) => return Err(self.invalid_char(c)),
+                None => return Err(JsonError::new(JsonErrorKind::Eof, Loc(self.pos, self.pos))),
+            };
+            // add the value to the enclosing array or object, closing it and
+            // the ones enclosing it as long as they end
+            loop {
+                match open.last_mut() {
+                    None => return Ok(value),
+                    Some(Open::Array(values)) => values.push(value),
+                    Some(Open::Object(fields, key)) => fields.push((mem::take(key), value)),
+                }
+                self.skip_spaces();
+                match (self.bump()?, open.last_mut().unwrap()) {
+                    (',', Open::Array(_)) => break,
+                    (',', Open::Object(_, key)) => {
+                        *key = self.key()?;
+                        break;
+                    }
+                    (']', Open::Array(values)) => value = Json::Array(mem::take(values)),
+                    ('}', Open::Object(fields, _)) => value = Json::Object(mem::take(fields)),
+                    (c, _) => {
+                        self.pos -= c.len_utf8();
+                        return Err(self.invalid_char(c));
+                    }
+                }
+                open.pop();
+            }
+        }
+    }
+
+    /// Read the key of an object field and the `:` after it
+    fn key(&mut self) -> Result<String> {
+        self.skip_spaces();
+        let key = self.string()?;
+        self.expect(':')?;
+        Ok(key)
+    }
+
+    fn keyword(&mut self) -> Result<Json> {
+        let start = self.pos;
+        while let Some('a'..='z') = self.peek() {
+            self.pos += 1;
+        }
+        match &self.input[start..self.pos] {
+            "null" => Ok(Json::Null),
+            "true" => Ok(Json::Bool(true)),
+            "false" => Ok(Json::Bool(false)),
+            _ => {
+                self.pos = start;
+                Err(self.invalid_char(self.peek().unwrap()))
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json> {
+        let start = self.pos;
+        while let Some('-') | Some('+') | Some('.') | Some('e') | Some('E') | Some('0'..='9') =
+            self.peek()
+        {
+            self.pos += 1;
+        }
+        let n = &self.input[start..self.pos];
+        match n.parse::<f64>() {
+            Ok(_) if !n.starts_with('+') => Ok(Json::Number(n.to_string())),
+            _ => Err(JsonError::new(
+                JsonErrorKind::InvalidNumber,
+                Loc(start, self.pos),
+            )),
+        }
+    }
+
+    fn string(&mut self) -> Result<String> {
+        self.expect('"')?;
+        let mut s = String::new();
+        loop {
+            let start = self.pos;
+            match self.bump()? {
+                '"' => return Ok(s),
+                '\\' => {
+                    let c = match self.bump()? {
+                        '"' => '"',
+                        '\\' => '\\',
+                        '/' => '/',
+                        'b' => '\u{8}',
+                        'f' => '\u{c}',
+                        'n' => '\n',
+                        'r' => '\r',
+                        't' => '\t',
+                        'u' => {
+                            let hex = self.input.get(self.pos..self.pos + 4);
+                            let c = hex
+                                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                                .and_then(std::char::from_u32);
+                            self.pos = (self.pos + 4).min(self.input.len());
+                            c.ok_or_else(|| {
+                                JsonError::new(JsonErrorKind::InvalidEscape, Loc(start, self.pos))
+                            })?
+                        }
+                        _ => {
+                            return Err(JsonError::new(
+                                JsonErrorKind::InvalidEscape,
+                                Loc(start, self.pos),
+                            ))
+                        }
+                    };
+                    s.push(c);
+                }
+                c => s.push(c),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json() {
+        let src = r#"{"a": [1, -2.5e3, true, null], "b\n": "x\"é"}"#;
+        let json = parse(src).unwrap();
+        assert_eq!(
+            json,
+            Json::Object(vec![
+                (
+                    "a".to_string(),
+                    Json::Array(vec![
+                        Json::Number("1".to_string()),
+                        Json::Number("-2.5e3".to_string()),
+                        Json::Bool(true),
+                        Json::Null,
+                    ])
+                ),
+                ("b\n".to_string(), Json::String("x\"é".to_string())),
+            ])
+        );
+        assert_eq!(parse(&json.to_string()), Ok(json));
+        assert_eq!(
+            parse("[1, 2"),
+            Err(JsonError::new(JsonErrorKind::Eof, Loc(5, 5)))
+        );
+
+        assert_eq!(
+            parse(r#"{"a": [1}"#),
+            Err(JsonError::new(JsonErrorKind::InvalidChar('}'), Loc(8, 9)))
+        );
+        assert_eq!(
+            parse("[1,]"),
+            Err(JsonError::new(JsonErrorKind::InvalidChar(']'), Loc(3, 4)))
+        );
+
+        let nested = format!("{}0{}", r#"[{"a":"#.repeat(100_000), "}]".repeat(100_000));
+        let json = parse(&nested).unwrap();
+        assert_eq!(json.to_string(), nested);
+        assert!(parse(&nested[1..]).is_err());
+    }
+}
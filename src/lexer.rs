@@ -1,4 +1,4 @@
-use std::cell::RefCell;
+use std::borrow::Cow;
 use std::cmp::{max, min};
 use std::fmt;
 use std::ops::FnMut;
@@ -7,8 +7,22 @@ use std::str::from_utf8;
 pub type Result<T> = std::result::Result<T, LexError>;
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum LexErrorKind {
     InvalidChar(char),
+    /// A radix prefix such as `0x` without any digit after it
+    MissingDigits,
+    /// A digit which is not valid in the radix of the literal
+    InvalidDigit {
+        digit: char,
+        radix: u32,
+    },
+    /// A number literal ending with the `_` separator
+    TrailingUnderscore,
+    /// A number literal which does not fit in `u64`
+    NumberTooLarge,
+    /// End of input inside a `/* */` comment
+    UnterminatedComment,
     Eof,
 }
 
@@ -21,6 +35,21 @@ impl LexError {
     fn eof(loc: Loc) -> Self {
         LexError::new(LexErrorKind::Eof, loc)
     }
+    fn missing_digits(loc: Loc) -> Self {
+        LexError::new(LexErrorKind::MissingDigits, loc)
+    }
+    fn invalid_digit(digit: char, radix: u32, loc: Loc) -> Self {
+        LexError::new(LexErrorKind::InvalidDigit { digit, radix }, loc)
+    }
+    fn trailing_underscore(loc: Loc) -> Self {
+        LexError::new(LexErrorKind::TrailingUnderscore, loc)
+    }
+    fn number_too_large(loc: Loc) -> Self {
+        LexError::new(LexErrorKind::NumberTooLarge, loc)
+    }
+    fn unterminated_comment(loc: Loc) -> Self {
+        LexError::new(LexErrorKind::UnterminatedComment, loc)
+    }
 }
 
 impl std::error::Error for LexError {}
@@ -31,12 +60,22 @@ impl fmt::Display for LexError {
         let loc = &self.loc;
         match self.value {
             InvalidChar(c) => write!(f, "{}: invalid char '{}'", loc, c),
-            Eof => write!(f, "End of file"),
+            MissingDigits => write!(f, "{}: no digits after the radix prefix", loc),
+            InvalidDigit { digit, radix } => write!(
+                f,
+                "{}: invalid digit '{}' in base {} literal",
+                loc, digit, radix
+            ),
+            TrailingUnderscore => write!(f, "{}: number literal ends with '_'", loc),
+            NumberTooLarge => write!(f, "{}: number literal is too large", loc),
+            UnterminatedComment => write!(f, "{}: comment is not closed", loc),
+            Eof => write!(f, "{}: unexpected end of input", loc),
         }
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Loc(pub usize, pub usize);
 
 impl Loc {
@@ -52,6 +91,7 @@ impl fmt::Display for Loc {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Annot<T> {
     pub value: T,
     pub loc: Loc,
@@ -63,10 +103,48 @@ impl<T> Annot<T> {
     }
 }
 
+/// Exact value of a decimal literal, `digits / 10^scale`
+///
+/// `1.50` is `Decimal { digits: 150, scale: 2 }`, so that it prints back as
+/// written. A scale of 0 prints as an integer.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub enum TokenKind {
-    /// [0-9]+
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Decimal {
+    pub digits: u64,
+    pub scale: u32,
+}
+
+impl Decimal {
+    /// Nearest `f64`
+    pub fn to_f64(self) -> f64 {
+        self.to_string().parse().unwrap()
+    }
+}
+
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scale = self.scale as usize;
+        let digits = format!("{:0>width$}", self.digits, width = scale + 1);
+        let (int, frac) = digits.split_at(digits.len() - scale);
+        if frac.is_empty() {
+            write!(f, "{}", int)
+        } else {
+            write!(f, "{}.{}", int, frac)
+        }
+    }
+}
+
+/// Kind of a token, whose symbols and identifiers borrow from the input
+/// when lexed
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TokenKind<'a> {
+    /// [0-9][0-9_]* | 0x[0-9a-fA-F_]+ | 0o[0-7_]+ | 0b[01_]+
     Number(u64),
+    /// [0-9][0-9_]*\.[0-9][0-9_]*
+    Decimal(Decimal),
+    /// Decimal number directly followed by `i` or `j`, e.g. `2.5i`
+    Imaginary(Decimal),
     /// +
     Plus,
     /// -
@@ -79,29 +157,101 @@ pub enum TokenKind {
     LParen,
     /// )
     RParen,
+    /// ,
+    Comma,
+    /// ;
+    Semicolon,
+    /// Operator symbol other than the ones above, e.g. `<+>`
+    Op(Cow<'a, str>),
+    /// [A-Za-z_][A-Za-z0-9_]*, or a reference to a previous result `$[0-9]+`
+    Ident(Cow<'a, str>),
 }
 
-impl fmt::Display for TokenKind {
+impl<'a> TokenKind<'a> {
+    /// Token of the operator symbol `s`
+    pub fn symbol(s: &'a str) -> Self {
+        use self::TokenKind::*;
+        match s {
+            "+" => Plus,
+            "-" => Minus,
+            "*" => Asterisk,
+            "/" => Slash,
+            _ => Op(Cow::Borrowed(s)),
+        }
+    }
+
+    /// Copy of the token which does not borrow from the input, e.g. to keep
+    /// it in an error
+    pub fn into_owned(self) -> TokenKind<'static> {
+        use self::TokenKind::*;
+        match self {
+            Number(n) => Number(n),
+            Decimal(d) => Decimal(d),
+            Imaginary(d) => Imaginary(d),
+            Plus => Plus,
+            Minus => Minus,
+            Asterisk => Asterisk,
+            Slash => Slash,
+            LParen => LParen,
+            RParen => RParen,
+            Comma => Comma,
+            Semicolon => Semicolon,
+            Op(s) => Op(Cow::Owned(s.into_owned())),
+            Ident(s) => Ident(Cow::Owned(s.into_owned())),
+        }
+    }
+
+    /// Operator symbol of the token, if it is one
+    pub fn as_symbol(&self) -> Option<&str> {
+        use self::TokenKind::*;
+        match self {
+            Plus => Some("+"),
+            Minus => Some("-"),
+            Asterisk => Some("*"),
+            Slash => Some("/"),
+            Op(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `c` can be part of an operator symbol
+pub fn is_symbol_char(c: char) -> bool {
+    "+-*/<>=!~^%&|@?".contains(c)
+}
+
+impl<'a> fmt::Display for TokenKind<'a> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::TokenKind::*;
         match self {
             Number(n) => n.fmt(f),
+            Decimal(d) => d.fmt(f),
+            Imaginary(d) => write!(f, "{}i", d),
             Plus => write!(f, "+"),
             Minus => write!(f, "-"),
             Asterisk => write!(f, "*"),
             Slash => write!(f, "/"),
             LParen => write!(f, "("),
             RParen => write!(f, ")"),
+            Comma => write!(f, ","),
+            Semicolon => write!(f, ";"),
+            Op(s) | Ident(s) => write!(f, "{}", s),
         }
     }
 }
 
-pub type Token = Annot<TokenKind>;
+pub type Token<'a> = Annot<TokenKind<'a>>;
 
-impl Token {
+impl<'a> Token<'a> {
     pub fn number(n: u64, loc: Loc) -> Self {
         Self::new(TokenKind::Number(n), loc)
     }
+    pub fn decimal(digits: u64, scale: u32, loc: Loc) -> Self {
+        Self::new(TokenKind::Decimal(Decimal { digits, scale }), loc)
+    }
+    pub fn imaginary(digits: u64, scale: u32, loc: Loc) -> Self {
+        Self::new(TokenKind::Imaginary(Decimal { digits, scale }), loc)
+    }
     pub fn plus(loc: Loc) -> Self {
         Self::new(TokenKind::Plus, loc)
     }
@@ -120,117 +270,337 @@ impl Token {
     pub fn rparen(loc: Loc) -> Self {
         Self::new(TokenKind::RParen, loc)
     }
+    pub fn comma(loc: Loc) -> Self {
+        Self::new(TokenKind::Comma, loc)
+    }
+    pub fn semicolon(loc: Loc) -> Self {
+        Self::new(TokenKind::Semicolon, loc)
+    }
+    pub fn op(s: &'a str, loc: Loc) -> Self {
+        Self::new(TokenKind::Op(Cow::Borrowed(s)), loc)
+    }
+    pub fn ident(s: &'a str, loc: Loc) -> Self {
+        Self::new(TokenKind::Ident(Cow::Borrowed(s)), loc)
+    }
+
+    pub fn into_owned(self) -> Token<'static> {
+        Token::new(self.value.into_owned(), self.loc)
+    }
 }
 
+/// Lexer yielding the tokens of `input` one at a time
+///
+/// Whitespace and comments (`# ...`, `// ...` and nestable `/* ... */`) are
+/// skipped. Iteration stops after the first error. The tokens borrow their
+/// symbols and identifiers from `input`, so lexing does not allocate.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Lexer<'a> {
     input: &'a [u8],
-    pos: RefCell<usize>,
+    pos: usize,
+    comments: Vec<Loc>,
 }
 
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Self {
         Self {
             input: input.as_bytes(),
-            pos: RefCell::new(0),
+            pos: 0,
+            comments: Vec::new(),
         }
     }
 
-    pub fn lex(&self) -> Result<Vec<Token>> {
-        let mut tokens = Vec::new();
+    /// Spans of the comments skipped so far
+    pub fn comments(&self) -> &[Loc] {
+        &self.comments
+    }
 
-        macro_rules! lex_a_token {
-            ($lexer:expr) => {{
-                let tok = $lexer?;
-                tokens.push(tok);
+    /// Collect all tokens of the input
+    pub fn lex(self) -> Result<Vec<Token<'a>>> {
+        self.collect()
+    }
+
+    fn next_token(&mut self) -> Option<Result<Token<'a>>> {
+        // skip over whitespace and comments, then lex a token
+        macro_rules! skip {
+            ($skipper:expr) => {{
+                match $skipper {
+                    Ok(()) => continue,
+                    Err(err) => Err(err),
+                }
             }};
         }
 
         loop {
-            let pos = *self.pos.borrow();
-            if self.input.len() <= pos {
-                break;
+            if self.input.len() <= self.pos {
+                return None;
             }
 
-            match self.input[pos] {
-                b'0'...b'9' => lex_a_token!(self.lex_number()),
-                b'+' => lex_a_token!(self.lex_plus()),
-                b'-' => lex_a_token!(self.lex_minus()),
-                b'*' => lex_a_token!(self.lex_asterisk()),
-                b'/' => lex_a_token!(self.lex_slash()),
-                b'(' => lex_a_token!(self.lex_lparen()),
-                b')' => lex_a_token!(self.lex_rparen()),
-                b' ' | b'\n' | b'\t' => self.skip_spaces()?,
-                b => return Err(LexError::invalid_char(b as char, Loc(pos, pos + 1))),
-            }
+            let tok = match self.input[self.pos] {
+                b'0'..=b'9' => self.lex_number(),
+                b'#' => skip!(self.skip_line_comment()),
+                b'/' if self.at_comment() => match self.input[self.pos + 1] {
+                    b'/' => skip!(self.skip_line_comment()),
+                    _ => skip!(self.skip_block_comment()),
+                },
+                b if is_symbol_char(b as char) => self.lex_symbol(),
+                b'a'..=b'z' | b'A'..=b'Z' | b'_' => self.lex_ident(),
+                b'$' if self.input.get(self.pos + 1).is_some_and(u8::is_ascii_digit) => {
+                    self.lex_result_ref()
+                }
+                b'(' => self.lex_lparen(),
+                b')' => self.lex_rparen(),
+                b',' => self.lex_comma(),
+                b';' => self.lex_semicolon(),
+                _ => match self.peek_char() {
+                    Some(c) if c.is_whitespace() => skip!(self.skip_spaces()),
+                    Some(c) => Err(LexError::invalid_char(
+                        c,
+                        Loc(self.pos, self.pos + c.len_utf8()),
+                    )),
+                    None => Err(LexError::eof(Loc(self.pos, self.pos))),
+                },
+            };
+            return Some(tok);
         }
+    }
 
-        Ok(tokens)
+    /// Decode the char at the current position
+    fn peek_char(&self) -> Option<char> {
+        let rest = &self.input[self.pos..];
+        let width = match *rest.first()? {
+            b if b < 0x80 => 1,
+            b if b >= 0xf0 => 4,
+            b if b >= 0xe0 => 3,
+            _ => 2,
+        };
+        from_utf8(&rest[..width.min(rest.len())])
+            .ok()?
+            .chars()
+            .next()
     }
 
-    fn consume_byte(&self, b: u8) -> Result<(u8, usize)> {
-        let mut pos = self.pos.borrow_mut();
-        if self.input.len() <= *pos {
-            return Err(LexError::eof(Loc(*pos, *pos)));
+    fn consume_byte(&mut self, b: u8) -> Result<(u8, usize)> {
+        if self.input.len() <= self.pos {
+            return Err(LexError::eof(Loc(self.pos, self.pos)));
         }
-        if self.input[*pos] != b {
+        if self.input[self.pos] != b {
             return Err(LexError::invalid_char(
-                self.input[*pos] as char,
-                Loc(*pos, *pos + 1),
+                self.input[self.pos] as char,
+                Loc(self.pos, self.pos + 1),
             ));
         }
 
-        *pos += 1;
+        self.pos += 1;
 
-        Ok((b, *pos))
+        Ok((b, self.pos))
     }
 
-    fn recognize_many(&self, mut f: impl FnMut(u8) -> bool) -> usize {
-        let mut pos = self.pos.borrow_mut();
-        while *pos < self.input.len() && f(self.input[*pos]) {
-            *pos += 1;
+    fn recognize_many(&mut self, mut f: impl FnMut(u8) -> bool) -> usize {
+        while self.pos < self.input.len() && f(self.input[self.pos]) {
+            self.pos += 1;
         }
-        *pos
+        self.pos
     }
 
-    fn lex_plus(&self) -> Result<Token> {
-        self.consume_byte(b'+')
-            .map(|(_, end)| Token::plus(Loc(end - 1, end)))
+    /// Whether a `//` or `/*` comment starts at the current position
+    fn at_comment(&self) -> bool {
+        matches!(self.input[self.pos..], [b'/', b'/', ..] | [b'/', b'*', ..])
     }
-    fn lex_minus(&self) -> Result<Token> {
-        self.consume_byte(b'-')
-            .map(|(_, end)| Token::minus(Loc(end - 1, end)))
+
+    /// Part of the input between `start` and `end`, which are char
+    /// boundaries
+    fn slice(&self, start: usize, end: usize) -> &'a str {
+        from_utf8(&self.input[start..end]).unwrap()
     }
-    fn lex_asterisk(&self) -> Result<Token> {
-        self.consume_byte(b'*')
-            .map(|(_, end)| Token::asterisk(Loc(end - 1, end)))
+
+    /// Lex the longest run of symbol chars, stopping before any comment
+    fn lex_symbol(&mut self) -> Result<Token<'a>> {
+        let start = self.pos;
+        while self.pos < self.input.len()
+            && is_symbol_char(self.input[self.pos] as char)
+            && !self.at_comment()
+        {
+            self.pos += 1;
+        }
+        let s = self.slice(start, self.pos);
+        Ok(Token::new(TokenKind::symbol(s), Loc(start, self.pos)))
     }
-    fn lex_slash(&self) -> Result<Token> {
-        self.consume_byte(b'/')
-            .map(|(_, end)| Token::slash(Loc(end - 1, end)))
+    fn lex_ident(&mut self) -> Result<Token<'a>> {
+        let start = self.pos;
+        let end = self.recognize_many(|b| b.is_ascii_alphanumeric() || b == b'_');
+        Ok(Token::ident(self.slice(start, end), Loc(start, end)))
     }
-    fn lex_lparen(&self) -> Result<Token> {
+    fn lex_result_ref(&mut self) -> Result<Token<'a>> {
+        let start = self.pos;
+        self.pos += 1;
+        let end = self.recognize_many(|b| b.is_ascii_digit());
+        Ok(Token::ident(self.slice(start, end), Loc(start, end)))
+    }
+    fn lex_lparen(&mut self) -> Result<Token<'a>> {
         self.consume_byte(b'(')
             .map(|(_, end)| Token::lparen(Loc(end - 1, end)))
     }
-    fn lex_rparen(&self) -> Result<Token> {
+    fn lex_rparen(&mut self) -> Result<Token<'a>> {
         self.consume_byte(b')')
             .map(|(_, end)| Token::rparen(Loc(end - 1, end)))
     }
+    fn lex_comma(&mut self) -> Result<Token<'a>> {
+        self.consume_byte(b',')
+            .map(|(_, end)| Token::comma(Loc(end - 1, end)))
+    }
+    fn lex_semicolon(&mut self) -> Result<Token<'a>> {
+        self.consume_byte(b';')
+            .map(|(_, end)| Token::semicolon(Loc(end - 1, end)))
+    }
+
+    fn lex_number(&mut self) -> Result<Token<'a>> {
+        let start = self.pos;
+        let radix = match self.input.get(start..start + 2) {
+            Some(b"0x") | Some(b"0X") => 16,
+            Some(b"0o") | Some(b"0O") => 8,
+            Some(b"0b") | Some(b"0B") => 2,
+            _ => 10,
+        };
+        if radix != 10 {
+            self.pos += 2;
+        }
+        let digits_start = self.pos;
+        // hexadecimal literals take every alphanumeric so that e.g. `0x1g` is
+        // reported as a whole
+        let end = if radix == 16 {
+            self.recognize_many(|b| b.is_ascii_alphanumeric() || b == b'_')
+        } else {
+            self.recognize_many(|b| b.is_ascii_digit() || b == b'_')
+        };
+        let loc = Loc(start, end);
+        let digits = &self.input[digits_start..end];
+
+        if digits.iter().all(|&b| b == b'_') {
+            return Err(LexError::missing_digits(loc));
+        }
+        if let Some(&b) = digits
+            .iter()
+            .find(|&&b| b != b'_' && !(b as char).is_digit(radix))
+        {
+            return Err(LexError::invalid_digit(b as char, radix, loc));
+        }
+        if digits.last() == Some(&b'_') {
+            return Err(LexError::trailing_underscore(loc));
+        }
+
+        let fraction = radix == 10
+            && self.input.get(end) == Some(&b'.')
+            && self.input.get(end + 1).is_some_and(u8::is_ascii_digit);
+        if !fraction {
+            let n = parse_digits(digits, radix, &loc)?;
+            if radix == 10 && self.lex_imaginary_suffix() {
+                return Ok(Token::imaginary(n, 0, Loc(start, self.pos)));
+            }
+            return Ok(Token::number(n, loc));
+        }
 
-    fn lex_number(&self) -> Result<Token> {
-        let start = *self.pos.borrow();
-        let end = self.recognize_many(|b| b"0123456789".contains(&b));
+        self.pos += 1;
+        let frac_start = self.pos;
+        let end = self.recognize_many(|b| b.is_ascii_digit() || b == b'_');
+        let loc = Loc(start, end);
+        if self.input[end - 1] == b'_' {
+            return Err(LexError::trailing_underscore(loc));
+        }
+        // the digits on both sides of the point, as one integer
+        let digits: Vec<u8> = self.input[digits_start..end]
+            .iter()
+            .copied()
+            .filter(|&b| b != b'.')
+            .collect();
+        let scale = self.input[frac_start..end]
+            .iter()
+            .filter(|&&b| b != b'_')
+            .count();
+        let digits = parse_digits(&digits, 10, &loc)?;
+        if self.lex_imaginary_suffix() {
+            return Ok(Token::imaginary(digits, scale as u32, Loc(start, self.pos)));
+        }
+        Ok(Token::decimal(digits, scale as u32, loc))
+    }
 
-        let n = from_utf8(&self.input[start..end]).unwrap().parse().unwrap();
+    /// Consume the `i` or `j` of an imaginary literal, unless it starts an
+    /// identifier such as the `in` of `3in`
+    fn lex_imaginary_suffix(&mut self) -> bool {
+        let suffix = matches!(self.input.get(self.pos), Some(b'i') | Some(b'j'))
+            && !self
+                .input
+                .get(self.pos + 1)
+                .is_some_and(|&b| b.is_ascii_alphanumeric() || b == b'_');
+        if suffix {
+            self.pos += 1;
+        }
+        suffix
+    }
 
-        Ok(Token::number(n, Loc(start, end)))
+    fn skip_spaces(&mut self) -> Result<()> {
+        while let Some(c) = self.peek_char().filter(|c| c.is_whitespace()) {
+            self.pos += c.len_utf8();
+        }
+        Ok(())
     }
 
-    fn skip_spaces(&self) -> Result<()> {
-        self.recognize_many(|b| b" \n\t".contains(&b));
+    /// Skip `# ...` or `// ...` up to the end of the line
+    fn skip_line_comment(&mut self) -> Result<()> {
+        let start = self.pos;
+        let end = self.recognize_many(|b| b != b'\n' && b != b'\r');
+        self.comments.push(Loc(start, end));
         Ok(())
     }
+
+    /// Skip `/* ... */`, which may be nested
+    fn skip_block_comment(&mut self) -> Result<()> {
+        let start = self.pos;
+        let mut depth = 0;
+        while self.pos < self.input.len() {
+            match &self.input[self.pos..] {
+                [b'/', b'*', ..] => {
+                    depth += 1;
+                    self.pos += 2;
+                }
+                [b'*', b'/', ..] => {
+                    depth -= 1;
+                    self.pos += 2;
+                    if depth == 0 {
+                        self.comments.push(Loc(start, self.pos));
+                        return Ok(());
+                    }
+                }
+                _ => self.pos += 1,
+            }
+        }
+        Err(LexError::unterminated_comment(Loc(start, start + 2)))
+    }
+}
+
+/// Value of the `digits` of a literal in `radix`, skipping `_` separators
+fn parse_digits(digits: &[u8], radix: u32, loc: &Loc) -> Result<u64> {
+    let mut n: u64 = 0;
+    for &b in digits.iter().filter(|&&b| b != b'_') {
+        let d = u64::from((b as char).to_digit(radix).unwrap());
+        n = n
+            .checked_mul(u64::from(radix))
+            .and_then(|n| n.checked_add(d))
+            .ok_or_else(|| LexError::number_too_large(loc.clone()))?;
+    }
+    Ok(n)
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ret = self.next_token();
+        if let Some(Err(_)) = ret {
+            // stop at the first error
+            self.pos = self.input.len();
+        }
+        ret
+    }
 }
 
 #[cfg(test)]
@@ -254,4 +624,132 @@ mod tests {
             ])
         )
     }
+
+    #[test]
+    fn test_lexer_stops_at_error() {
+        let tokens = Lexer::new("1 $ 2").collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            vec![
+                Ok(Token::number(1, Loc(0, 1))),
+                Err(LexError::invalid_char('$', Loc(2, 3))),
+            ]
+        )
+    }
+
+    #[test]
+    fn test_lexer_number_literals() {
+        let lex = |s| Lexer::new(s).lex();
+        assert_eq!(
+            lex("0x1F + 0o17 * 0b1010 - 1_000_000"),
+            Ok(vec![
+                Token::number(31, Loc(0, 4)),
+                Token::plus(Loc(5, 6)),
+                Token::number(15, Loc(7, 11)),
+                Token::asterisk(Loc(12, 13)),
+                Token::number(10, Loc(14, 20)),
+                Token::minus(Loc(21, 22)),
+                Token::number(1_000_000, Loc(23, 32)),
+            ])
+        );
+        assert_eq!(lex("1 + 0x"), Err(LexError::missing_digits(Loc(4, 6))));
+        assert_eq!(
+            lex("0b102"),
+            Err(LexError::invalid_digit('2', 2, Loc(0, 5)))
+        );
+        assert_eq!(lex("1_"), Err(LexError::trailing_underscore(Loc(0, 2))));
+        assert_eq!(
+            lex("2.5 1_000.000_1 0.05"),
+            Ok(vec![
+                Token::decimal(25, 1, Loc(0, 3)),
+                Token::decimal(10_000_001, 4, Loc(4, 15)),
+                Token::decimal(5, 2, Loc(16, 20)),
+            ])
+        );
+        let decimal = |digits, scale| Decimal { digits, scale }.to_string();
+        assert_eq!(
+            (decimal(5, 2), decimal(150, 2)),
+            ("0.05".into(), "1.50".into())
+        );
+        assert_eq!(decimal(3, 0), "3");
+        assert_eq!(
+            lex("3i 2.5j 3in 0x1 1i2"),
+            Ok(vec![
+                Token::imaginary(3, 0, Loc(0, 2)),
+                Token::imaginary(25, 1, Loc(3, 7)),
+                Token::number(3, Loc(8, 9)),
+                Token::ident("in", Loc(9, 11)),
+                Token::number(1, Loc(12, 15)),
+                Token::number(1, Loc(16, 17)),
+                Token::ident("i2", Loc(17, 19)),
+            ])
+        );
+        assert_eq!(lex("1._5"), Err(LexError::invalid_char('.', Loc(1, 2))));
+        assert_eq!(lex("1.5_"), Err(LexError::trailing_underscore(Loc(0, 4))));
+        assert_eq!(
+            lex("18446744073709551616"),
+            Err(LexError::number_too_large(Loc(0, 20)))
+        );
+    }
+
+    #[test]
+    fn test_lexer_symbols() {
+        assert_eq!(
+            Lexer::new("infixl 6 <+> (a, b) = a*-b//c").lex(),
+            Ok(vec![
+                Token::ident("infixl", Loc(0, 6)),
+                Token::number(6, Loc(7, 8)),
+                Token::op("<+>", Loc(9, 12)),
+                Token::lparen(Loc(13, 14)),
+                Token::ident("a", Loc(14, 15)),
+                Token::comma(Loc(15, 16)),
+                Token::ident("b", Loc(17, 18)),
+                Token::rparen(Loc(18, 19)),
+                Token::op("=", Loc(20, 21)),
+                Token::ident("a", Loc(22, 23)),
+                Token::op("*-", Loc(23, 25)),
+                Token::ident("b", Loc(25, 26)),
+            ])
+        );
+        assert_eq!(
+            Lexer::new("$12+_;").lex(),
+            Ok(vec![
+                Token::ident("$12", Loc(0, 3)),
+                Token::plus(Loc(3, 4)),
+                Token::ident("_", Loc(4, 5)),
+                Token::semicolon(Loc(5, 6)),
+            ])
+        );
+        assert_eq!(
+            Lexer::new("$x").lex(),
+            Err(LexError::invalid_char('$', Loc(0, 1)))
+        );
+
+        let tokens = Lexer::new("km <+> h").lex().unwrap();
+        assert!(tokens.iter().all(|token| matches!(
+            token.value,
+            TokenKind::Ident(Cow::Borrowed(_)) | TokenKind::Op(Cow::Borrowed(_))
+        )));
+    }
+
+    #[test]
+    fn test_lexer_comments() {
+        let src = "# header\r\n1 /* a /* nested */ comment */ +\u{3000}2 // tail";
+        let mut lexer = Lexer::new(src);
+        let tokens = lexer.by_ref().collect::<Result<Vec<_>>>();
+        assert_eq!(
+            tokens,
+            Ok(vec![
+                Token::number(1, Loc(10, 11)),
+                Token::plus(Loc(41, 42)),
+                Token::number(2, Loc(45, 46)),
+            ])
+        );
+        assert_eq!(lexer.comments(), &[Loc(0, 8), Loc(12, 40), Loc(47, 54)]);
+
+        assert_eq!(
+            Lexer::new("1 /* /* */").lex(),
+            Err(LexError::unterminated_comment(Loc(2, 4)))
+        );
+    }
 }
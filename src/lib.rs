@@ -1,15 +1,29 @@
-use std::io;
-use std::io::{BufRead, Write};
+use std::path::PathBuf;
 use structopt::StructOpt;
 
-use interpreter::Interpreter;
-use parser::Ast;
-use rpn_compiler::RpnCompiler;
+use emit::Emit;
+use formatter::Notation;
 
-mod interpreter;
-mod lexer;
-mod parser;
+pub mod complete;
+pub mod complex;
+pub mod dump;
+pub mod emit;
+pub mod formatter;
+mod highlight;
+pub mod interpreter;
+pub mod json;
+pub mod lexer;
+mod line_editor;
+pub mod lint;
+pub mod operator;
+pub mod parser;
+pub mod printer;
+mod repl;
 mod rpn_compiler;
+mod script;
+mod source_fmt;
+pub mod units;
+pub mod visit;
 
 /// Command line options
 #[derive(StructOpt, Debug)]
@@ -18,6 +32,75 @@ pub struct Opt {
     /// Use RPN compiler mode
     #[structopt(short = "c", long = "compiler")]
     pub use_compiler: bool,
+
+    /// Print the tokens of every input line
+    #[structopt(long = "dump-tokens")]
+    pub dump_tokens: bool,
+
+    /// Print the syntax tree of every input line
+    #[structopt(long = "dump-ast")]
+    pub dump_ast: bool,
+
+    /// Read juxtaposed operands such as `2(3+4)` as a multiplication
+    #[structopt(long = "implicit-mul")]
+    pub implicit_mul: bool,
+
+    /// Trace the parser rules to stderr
+    #[structopt(long = "trace-parse")]
+    pub trace_parse: bool,
+
+    /// Print every input line as `dot` (Graphviz) or `ast-json` instead of evaluating it
+    #[structopt(long = "emit", raw(possible_values = r#"&["dot", "ast-json"]"#))]
+    pub emit: Option<Emit>,
+
+    /// Radix of the printed results
+    #[structopt(
+        long = "obase",
+        default_value = "10",
+        raw(possible_values = r#"&["2", "8", "10", "16"]"#)
+    )]
+    pub obase: u32,
+
+    /// Group the digits of the printed results
+    #[structopt(long = "group")]
+    pub group: bool,
+
+    /// Notation of the printed non-integer results: `auto`, `fixed:N` or `sci:N`,
+    /// with N digits after the decimal point
+    #[structopt(long = "notation", default_value = "auto")]
+    pub notation: Notation,
+
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
+}
+
+/// Subcommands
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    /// Rewrite script files in canonical style
+    #[structopt(name = "fmt")]
+    Fmt {
+        /// Do not write files; exit with non-zero status if any file would change
+        #[structopt(long = "check")]
+        check: bool,
+
+        /// Maximum line width
+        #[structopt(long = "width", default_value = "80")]
+        width: usize,
+
+        /// Script files to format
+        #[structopt(name = "FILE", parse(from_os_str))]
+        files: Vec<PathBuf>,
+    },
+
+    /// Run a script of statements separated by `;` or line breaks, printing
+    /// the value of every expression
+    #[structopt(name = "run")]
+    Run {
+        /// Script file
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: PathBuf,
+    },
 }
 
 fn show_trace<E: std::error::Error>(err: E) {
@@ -29,52 +112,14 @@ fn show_trace<E: std::error::Error>(err: E) {
     }
 }
 
-fn prompt(s: &str) -> io::Result<()> {
-    let stdout = io::stdout();
-    let mut stdout = stdout.lock();
-    stdout.write(s.as_bytes())?;
-    stdout.flush()
-}
-
 pub fn run(opt: &Opt) -> i32 {
-    let mut interp = Interpreter::new();
-    let mut compiler = RpnCompiler::new();
-
-    let stdin = io::stdin();
-    let stdin = stdin.lock();
-    let stdin = io::BufReader::new(stdin);
-    let mut lines = stdin.lines();
-
-    loop {
-        prompt("> ").unwrap();
-        if let Some(Ok(line)) = lines.next() {
-            let ast = match line.parse::<Ast>() {
-                Ok(ast) => ast,
-                Err(err) => {
-                    err.show_diagnostic(&line);
-                    show_trace(err);
-                    continue;
-                }
-            };
-
-            if opt.use_compiler {
-                let rpn = compiler.compile(&ast);
-                println!("{}", rpn);
-            } else {
-                let n = match interp.eval(&ast) {
-                    Ok(n) => n,
-                    Err(err) => {
-                        err.show_diagnostic(&line);
-                        show_trace(err);
-                        continue;
-                    }
-                };
-                println!("{}", n);
-            }
-        } else {
-            break;
-        }
+    match opt.cmd {
+        Some(Command::Fmt {
+            check,
+            width,
+            ref files,
+        }) => source_fmt::run(files, check, width),
+        Some(Command::Run { ref file }) => script::run(file, opt),
+        None => repl::run(opt),
     }
-
-    0
 }
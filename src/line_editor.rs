@@ -0,0 +1,738 @@
+//! Line editing for the REPL
+//!
+//! On a terminal, lines are edited in raw mode with Emacs-style key bindings:
+//! cursor movement, kill and yank, history navigation and reverse search.
+//! When stdin is not a terminal, lines are read as they are.
+//!
+//! The line can be colored by a `Highlighter` as it is edited, unless the
+//! `NO_COLOR` environment variable is set or the terminal is dumb, and the
+//! word before the cursor is completed by a `Completer` on Tab.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::path::PathBuf;
+
+/// Maximum number of history entries kept
+const HISTORY_SIZE: usize = 1000;
+
+/// SGR parameters of a char, e.g. `"1;31"` for bold red, empty for the
+/// default style
+pub type Style = &'static str;
+
+/// Colors the line being edited
+pub trait Highlighter {
+    /// Style of every char of `line` with the cursor before the char at
+    /// `cursor`
+    fn highlight(&self, line: &str, cursor: usize) -> Vec<Style>;
+}
+
+/// A decoded key press
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    /// Control and a letter, e.g. `Ctrl('a')`
+    Ctrl(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    WordLeft,
+    WordRight,
+    Unknown,
+}
+
+/// Completes the word before the cursor
+pub trait Completer {
+    /// Byte offset of the start of the word before the byte offset `cursor`
+    /// of `line`, and the candidates to replace it with
+    fn complete(&self, line: &str, cursor: usize) -> (usize, Vec<String>);
+}
+
+/// Outcome of a key press
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    Continue,
+    Submit(String),
+    Eof,
+    Cancel,
+    ClearScreen,
+    /// Candidates of an ambiguous completion to show
+    List(Vec<String>),
+}
+
+/// State of a reverse incremental search
+#[derive(Debug, Clone, Default)]
+struct Search {
+    query: String,
+    /// History index of the current match
+    index: Option<usize>,
+    /// Line being edited when the search started
+    original: Vec<char>,
+}
+
+/// The line being edited
+#[derive(Debug, Clone, Default)]
+struct Editor {
+    buf: Vec<char>,
+    cursor: usize,
+    killed: Vec<char>,
+    /// History entry shown, and the line being edited before navigating
+    history_index: Option<usize>,
+    saved: Vec<char>,
+    search: Option<Search>,
+}
+
+impl Editor {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn line(&self) -> String {
+        self.buf.iter().collect()
+    }
+
+    fn set_line(&mut self, line: &str) {
+        self.buf = line.chars().collect();
+        self.cursor = self.buf.len();
+    }
+
+    fn handle(&mut self, key: Key, history: &[String]) -> Action {
+        if self.search.is_some() {
+            match self.handle_search(key, history) {
+                Some(action) => return action,
+                None => self.search = None,
+            }
+        }
+
+        match key {
+            Key::Char(c) => {
+                self.buf.insert(self.cursor, c);
+                self.cursor += 1;
+            }
+            Key::Enter => return Action::Submit(self.line()),
+            Key::Ctrl('c') => return Action::Cancel,
+            Key::Ctrl('d') if self.buf.is_empty() => return Action::Eof,
+            Key::Ctrl('d') | Key::Delete if self.cursor < self.buf.len() => {
+                self.buf.remove(self.cursor);
+            }
+            Key::Backspace | Key::Ctrl('h') if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.buf.remove(self.cursor);
+            }
+            Key::Left | Key::Ctrl('b') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right | Key::Ctrl('f') => self.cursor = (self.cursor + 1).min(self.buf.len()),
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = self.buf.len(),
+            Key::WordLeft => self.cursor = self.word_start(),
+            Key::WordRight => self.cursor = self.word_end(),
+            Key::Ctrl('k') => self.killed = self.buf.split_off(self.cursor),
+            Key::Ctrl('u') => {
+                self.killed = self.buf.drain(..self.cursor).collect();
+                self.cursor = 0;
+            }
+            Key::Ctrl('w') => {
+                let start = self.word_start();
+                self.killed = self.buf.drain(start..self.cursor).collect();
+                self.cursor = start;
+            }
+            Key::Ctrl('y') => {
+                let tail = self.buf.split_off(self.cursor);
+                self.buf.extend_from_slice(&self.killed);
+                self.cursor = self.buf.len();
+                self.buf.extend(tail);
+            }
+            Key::Up | Key::Ctrl('p') => self.history_prev(history),
+            Key::Down | Key::Ctrl('n') => self.history_next(history),
+            Key::Ctrl('r') => {
+                self.search = Some(Search {
+                    original: self.buf.clone(),
+                    ..Search::default()
+                });
+            }
+            Key::Ctrl('l') => return Action::ClearScreen,
+            _ => {}
+        }
+        Action::Continue
+    }
+
+    /// Handle `key` during a reverse search, returning `None` if it ends the
+    /// search and has to be handled as an editing key
+    fn handle_search(&mut self, key: Key, history: &[String]) -> Option<Action> {
+        let search = self.search.as_mut().unwrap();
+        let from = match key {
+            Key::Char(c) => {
+                search.query.push(c);
+                search.index.map_or(history.len(), |i| i + 1)
+            }
+            Key::Backspace | Key::Ctrl('h') => {
+                search.query.pop();
+                history.len()
+            }
+            Key::Ctrl('r') => search.index.unwrap_or(history.len()),
+            Key::Ctrl('c') | Key::Ctrl('g') => {
+                self.buf = search.original.clone();
+                self.cursor = self.buf.len();
+                self.search = None;
+                return Some(Action::Continue);
+            }
+            Key::Enter => {
+                self.search = None;
+                return Some(Action::Submit(self.line()));
+            }
+            _ => return None,
+        };
+
+        // find the latest entry before `from` containing the query
+        let found = history[..from]
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, entry)| entry.find(search.query.as_str()).map(|pos| (i, pos)));
+        if let Some((i, pos)) = found {
+            search.index = Some(i);
+            self.buf = history[i].chars().collect();
+            self.cursor = history[i][..pos].chars().count();
+        }
+        Some(Action::Continue)
+    }
+
+    fn history_prev(&mut self, history: &[String]) {
+        let index = match self.history_index {
+            None if history.is_empty() => return,
+            None => {
+                self.saved = self.buf.clone();
+                history.len() - 1
+            }
+            Some(0) => return,
+            Some(i) => i - 1,
+        };
+        self.history_index = Some(index);
+        self.set_line(&history[index]);
+    }
+
+    fn history_next(&mut self, history: &[String]) {
+        match self.history_index {
+            None => {}
+            Some(i) if i + 1 < history.len() => {
+                self.history_index = Some(i + 1);
+                self.set_line(&history[i + 1]);
+            }
+            Some(_) => {
+                self.history_index = None;
+                self.buf = std::mem::take(&mut self.saved);
+                self.cursor = self.buf.len();
+            }
+        }
+    }
+
+    /// Complete the word before the cursor up to the longest prefix common
+    /// to the candidates, or list them if it is already complete
+    fn complete(&mut self, completer: &dyn Completer) -> Action {
+        let line = self.line();
+        let cursor: usize = self.buf[..self.cursor].iter().map(|c| c.len_utf8()).sum();
+        let (start, candidates) = completer.complete(&line, cursor);
+        let first = match candidates.first() {
+            Some(first) => first,
+            None => return Action::Continue,
+        };
+        let common = candidates.iter().fold(first.as_str(), |common, candidate| {
+            let len = common
+                .char_indices()
+                .zip(candidate.chars())
+                .find(|((_, a), b)| a != b)
+                .map_or(common.len().min(candidate.len()), |((i, _), _)| i);
+            &common[..len]
+        });
+        if common.len() > cursor - start {
+            let start = line[..start].chars().count();
+            self.buf.splice(start..self.cursor, common.chars());
+            self.cursor = start + common.chars().count();
+            Action::Continue
+        } else if candidates.len() > 1 {
+            Action::List(candidates)
+        } else {
+            Action::Continue
+        }
+    }
+
+    /// Start of the word before the cursor
+    fn word_start(&self) -> usize {
+        let mut i = self.cursor;
+        while i > 0 && !self.buf[i - 1].is_alphanumeric() {
+            i -= 1;
+        }
+        while i > 0 && self.buf[i - 1].is_alphanumeric() {
+            i -= 1;
+        }
+        i
+    }
+
+    /// End of the word after the cursor
+    fn word_end(&self) -> usize {
+        let mut i = self.cursor;
+        while i < self.buf.len() && !self.buf[i].is_alphanumeric() {
+            i += 1;
+        }
+        while i < self.buf.len() && self.buf[i].is_alphanumeric() {
+            i += 1;
+        }
+        i
+    }
+
+    /// Escape sequences redrawing the line on a terminal `width` columns wide
+    ///
+    /// Lines too long for the terminal are scrolled horizontally to keep the
+    /// cursor visible. The chars of the line are drawn in `styles`, if any.
+    fn render(&self, prompt: &str, width: usize, styles: &[Style]) -> String {
+        let prompt = match self.search {
+            Some(ref search) => format!("(reverse-i-search)`{}': ", search.query),
+            None => prompt.to_string(),
+        };
+        let prompt_width = prompt.chars().count();
+        let columns = width.saturating_sub(prompt_width + 1).max(1);
+        let start = self.cursor.saturating_sub(columns);
+        let end = self.buf.len().min(start + columns);
+
+        let mut out = String::from("\r");
+        out.push_str(&prompt);
+        let mut current = "";
+        for (i, &c) in self.buf.iter().enumerate().take(end).skip(start) {
+            let style = styles.get(i).copied().unwrap_or("");
+            if style != current {
+                out.push_str("\x1b[0m");
+                if !style.is_empty() {
+                    out.push_str(&format!("\x1b[{}m", style));
+                }
+                current = style;
+            }
+            out.push(c);
+        }
+        if !current.is_empty() {
+            out.push_str("\x1b[0m");
+        }
+        out.push_str("\x1b[K\r");
+        let column = prompt_width + self.cursor - start;
+        if column > 0 {
+            out.push_str(&format!("\x1b[{}C", column));
+        }
+        out
+    }
+}
+
+/// Decode the next key from the bytes of a terminal, `None` at the end of
+/// input
+fn read_key<R: Read>(input: &mut R) -> io::Result<Option<Key>> {
+    let byte = match read_byte(input)? {
+        Some(byte) => byte,
+        None => return Ok(None),
+    };
+    let key = match byte {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f => Key::Backspace,
+        0x1b => read_escape(input)?,
+        1..=26 => Key::Ctrl((b'a' + byte - 1) as char),
+        0..=0x7f => Key::Char(byte as char),
+        _ => {
+            // the rest of a UTF-8 sequence, whose length the leading byte tells
+            let len = match byte {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            let mut bytes = vec![byte];
+            for _ in 1..len {
+                bytes.extend(read_byte(input)?);
+            }
+            match std::str::from_utf8(&bytes) {
+                Ok(s) => s.chars().next().map_or(Key::Unknown, Key::Char),
+                Err(_) => Key::Unknown,
+            }
+        }
+    };
+    Ok(Some(key))
+}
+
+/// Decode the key of an escape sequence after its `ESC`
+fn read_escape<R: Read>(input: &mut R) -> io::Result<Key> {
+    let key = match read_byte(input)? {
+        Some(b'b') => Key::WordLeft,
+        Some(b'f') => Key::WordRight,
+        Some(b'[') => {
+            // CSI: parameters, then a final byte in `@..=~`
+            let mut params = Vec::new();
+            let last = loop {
+                match read_byte(input)? {
+                    Some(byte @ b'@'..=b'~') => break byte,
+                    Some(byte) => params.push(byte),
+                    None => return Ok(Key::Unknown),
+                }
+            };
+            match (&params[..], last) {
+                (b"1;5", b'D') | (b"1;3", b'D') => Key::WordLeft,
+                (b"1;5", b'C') | (b"1;3", b'C') => Key::WordRight,
+                (_, b'A') => Key::Up,
+                (_, b'B') => Key::Down,
+                (_, b'C') => Key::Right,
+                (_, b'D') => Key::Left,
+                (_, b'H') | (b"1", b'~') | (b"7", b'~') => Key::Home,
+                (_, b'F') | (b"4", b'~') | (b"8", b'~') => Key::End,
+                (b"3", b'~') => Key::Delete,
+                _ => Key::Unknown,
+            }
+        }
+        Some(b'O') => match read_byte(input)? {
+            Some(b'H') => Key::Home,
+            Some(b'F') => Key::End,
+            _ => Key::Unknown,
+        },
+        _ => Key::Unknown,
+    };
+    Ok(key)
+}
+
+fn read_byte<R: Read>(input: &mut R) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    loop {
+        match input.read(&mut byte) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(byte[0])),
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Previously entered lines, persisted in a file if one is available
+struct History {
+    entries: Vec<String>,
+    path: Option<PathBuf>,
+}
+
+impl History {
+    fn load(path: Option<PathBuf>) -> Self {
+        let mut entries: Vec<String> = path
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .map(|src| src.lines().map(String::from).collect())
+            .unwrap_or_default();
+        if entries.len() > HISTORY_SIZE {
+            entries.drain(..entries.len() - HISTORY_SIZE);
+            if let Some(ref path) = path {
+                let _ = fs::write(path, entries.join("\n") + "\n");
+            }
+        }
+        History { entries, path }
+    }
+
+    /// Append `line` unless it is blank or repeats the last entry
+    fn add(&mut self, line: &str) {
+        if line.trim().is_empty() || self.entries.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+        if self.entries.len() > HISTORY_SIZE {
+            self.entries.remove(0);
+        }
+        // history is a convenience, so failing to save it is not reported
+        if let Some(ref path) = self.path {
+            let _ = append_line(path, line);
+        }
+    }
+}
+
+fn append_line(path: &PathBuf, line: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+    writeln!(file, "{}", line)
+}
+
+/// Path of the history file in the data directory of the user
+fn history_path() -> Option<PathBuf> {
+    let data_dir = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+    };
+    data_dir.map(|dir| dir.join("myparse").join("history"))
+}
+
+/// Outcome of `LineEditor::read_line`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    Line(String),
+    /// The line was abandoned with Ctrl-C
+    Interrupted,
+    Eof,
+}
+
+/// Reads lines from stdin, with editing if it is a terminal
+pub struct LineEditor {
+    tty: bool,
+    history: History,
+    highlighter: Option<Box<dyn Highlighter>>,
+}
+
+impl LineEditor {
+    pub fn new() -> Self {
+        let tty = terminal::is_tty();
+        LineEditor {
+            tty,
+            history: History::load(if tty { history_path() } else { None }),
+            highlighter: None,
+        }
+    }
+
+    /// Color the line with `highlighter` if colors are enabled
+    pub fn highlighter(mut self, highlighter: Box<dyn Highlighter>) -> Self {
+        if colors_enabled() {
+            self.highlighter = Some(highlighter);
+        }
+        self
+    }
+
+    /// Read a line after showing `prompt`, completing words with `completer`
+    pub fn read_line(&mut self, prompt: &str, completer: &dyn Completer) -> io::Result<Input> {
+        if !self.tty {
+            return read_plain_line(prompt);
+        }
+
+        let _raw = terminal::RawMode::enable()?;
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        let mut editor = Editor::new();
+        loop {
+            let styles = match self.highlighter {
+                Some(ref highlighter) => highlighter.highlight(&editor.line(), editor.cursor),
+                None => Vec::new(),
+            };
+            let line = editor.render(prompt, terminal::width(), &styles);
+            stdout.write_all(line.as_bytes())?;
+            stdout.flush()?;
+            let key = match read_key(&mut stdin)? {
+                Some(key) => key,
+                None => return Ok(Input::Eof),
+            };
+            let action = match key {
+                Key::Tab if editor.search.is_none() => editor.complete(completer),
+                key => editor.handle(key, &self.history.entries),
+            };
+            match action {
+                Action::Continue => {}
+                Action::Submit(line) => {
+                    stdout.write_all(b"\r\n")?;
+                    self.history.add(&line);
+                    return Ok(Input::Line(line));
+                }
+                Action::Eof => {
+                    stdout.write_all(b"\r\n")?;
+                    return Ok(Input::Eof);
+                }
+                Action::Cancel => {
+                    stdout.write_all(b"^C\r\n")?;
+                    return Ok(Input::Interrupted);
+                }
+                Action::ClearScreen => stdout.write_all(b"\x1b[H\x1b[2J")?,
+                Action::List(candidates) => {
+                    write!(stdout, "\r\n{}\r\n", candidates.join("  "))?;
+                }
+            }
+        }
+    }
+}
+
+/// Whether the terminal may be colored, see <https://no-color.org>
+fn colors_enabled() -> bool {
+    let no_color = env::var_os("NO_COLOR").is_some_and(|s| !s.is_empty());
+    let dumb = env::var_os("TERM").is_some_and(|term| term == "dumb");
+    !no_color && !dumb
+}
+
+fn read_plain_line(prompt: &str) -> io::Result<Input> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    stdout.write_all(prompt.as_bytes())?;
+    stdout.flush()?;
+
+    let mut line = String::new();
+    if io::stdin().lock().read_line(&mut line)? == 0 {
+        return Ok(Input::Eof);
+    }
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Input::Line(line))
+}
+
+#[cfg(unix)]
+mod terminal {
+    use std::io;
+    use std::mem;
+
+    pub fn is_tty() -> bool {
+        unsafe { libc::isatty(libc::STDIN_FILENO) == 1 && libc::isatty(libc::STDOUT_FILENO) == 1 }
+    }
+
+    /// Number of columns of the terminal
+    pub fn width() -> usize {
+        let mut size: libc::winsize = unsafe { mem::zeroed() };
+        match unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) } {
+            0 if size.ws_col > 0 => size.ws_col as usize,
+            _ => 80,
+        }
+    }
+
+    /// Puts the terminal in raw mode until dropped
+    pub struct RawMode(libc::termios);
+
+    impl RawMode {
+        pub fn enable() -> io::Result<Self> {
+            unsafe {
+                let mut original: libc::termios = mem::zeroed();
+                if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                let mut raw = original;
+                raw.c_iflag &=
+                    !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
+                raw.c_cflag |= libc::CS8;
+                raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+                raw.c_cc[libc::VMIN] = 1;
+                raw.c_cc[libc::VTIME] = 0;
+                if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &raw) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(RawMode(original))
+            }
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.0);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod terminal {
+    use std::io;
+
+    /// Raw mode is only supported on Unix, elsewhere lines are read as they are
+    pub fn is_tty() -> bool {
+        false
+    }
+
+    pub fn width() -> usize {
+        80
+    }
+
+    pub struct RawMode;
+
+    impl RawMode {
+        pub fn enable() -> io::Result<Self> {
+            Err(io::Error::new(
+                io::ErrorKind::Other,
+                "raw mode is not supported",
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(s: &str) -> Vec<Key> {
+        let mut input = s.as_bytes();
+        let mut keys = Vec::new();
+        while let Some(key) = read_key(&mut input).unwrap() {
+            keys.push(key);
+        }
+        keys
+    }
+
+    #[test]
+    fn test_editing() {
+        let history = vec!["1 + 2".to_string(), "3 * 4".to_string()];
+        let run = |input: &str| {
+            let mut editor = Editor::new();
+            for key in keys(input) {
+                match editor.handle(key, &history) {
+                    Action::Continue => {}
+                    action => return action,
+                }
+            }
+            Action::Continue
+        };
+        let submit = |s: &str| Action::Submit(s.to_string());
+        // cursor movement, kill and yank
+        assert_eq!(run("2*3\x1b[D\x1b[D(\x05)\r"), submit("2(*3)"));
+        assert_eq!(run("1 + 23\x17\x01\x19 \r"), submit("23 1 + "));
+        assert_eq!(run("10 - 20\x1bb\x0b\x05\x7f\x7f\x19\r"), submit("10 20"));
+        // history navigation back to the line being edited
+        assert_eq!(run("\x1b[A\x1b[A\x1b[A\r"), submit("1 + 2"));
+        assert_eq!(run("7\x10\x0e\x0e\r"), submit("7"));
+        // reverse search
+        assert_eq!(run("\x12+\r"), submit("1 + 2"));
+        assert_eq!(run("\x12 \x12\x12\x05!\r"), submit("1 + 2!"));
+        assert_eq!(run("5\x12*\x07\r"), submit("5"));
+        assert_eq!(run("\x04"), Action::Eof);
+    }
+
+    #[test]
+    fn test_completion() {
+        struct Words;
+        impl Completer for Words {
+            fn complete(&self, line: &str, cursor: usize) -> (usize, Vec<String>) {
+                let start = line[..cursor].rfind(' ').map_or(0, |i| i + 1);
+                let words = ["infix", "infixl", "infixr", "ans"];
+                let candidates = words
+                    .iter()
+                    .filter(|word| word.starts_with(&line[start..cursor]))
+                    .map(|word| word.to_string())
+                    .collect();
+                (start, candidates)
+            }
+        }
+        let mut editor = Editor::new();
+        editor.set_line("é i 1");
+        editor.cursor = 3;
+        assert_eq!(editor.complete(&Words), Action::Continue);
+        assert_eq!(editor.line(), "é infix 1");
+        let candidates = ["infix", "infixl", "infixr"];
+        assert_eq!(
+            editor.complete(&Words),
+            Action::List(candidates.iter().map(|s| s.to_string()).collect())
+        );
+        editor.set_line("1 + a");
+        assert_eq!(editor.complete(&Words), Action::Continue);
+        assert_eq!(editor.line(), "1 + ans");
+        assert_eq!(editor.cursor, 7);
+    }
+}
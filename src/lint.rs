@@ -0,0 +1,78 @@
+//! Warnings about valid but suspicious expressions
+
+use std::fmt;
+
+use super::lexer::Annot;
+use super::parser::{print_annot, Ast, AstNode, UniOpKind};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum LintKind {
+    /// `- -e`, which is just `e`
+    DoubleNegation,
+}
+
+pub type Lint = Annot<LintKind>;
+
+impl Lint {
+    pub fn show_diagnostic(&self, input: &str) {
+        eprintln!("{}", self);
+        print_annot(input, &self.loc);
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::LintKind::*;
+        match self.value {
+            DoubleNegation => write!(f, "warning: {}: double negation has no effect", self.loc),
+        }
+    }
+}
+
+/// Collect the warnings about `ast`, in source order
+///
+/// The tree is walked with an explicit stack, so that machine-generated
+/// expressions of any depth can be linted.
+pub fn lint(ast: &Ast) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let mut stack = vec![ast];
+    while let Some(ast) = stack.pop() {
+        match ast.value {
+            AstNode::Num(_) | AstNode::Decimal(_) | AstNode::Imaginary(_) | AstNode::Var(_) => {}
+            AstNode::UniOp { ref op, ref e } => {
+                if let (UniOpKind::Minus, AstNode::UniOp { op: inner, .. }) = (&op.value, &e.value)
+                {
+                    if inner.value == UniOpKind::Minus {
+                        lints.push(Lint::new(
+                            LintKind::DoubleNegation,
+                            op.loc.merge(&inner.loc),
+                        ));
+                    }
+                }
+                stack.push(e);
+            }
+            AstNode::BinOp { ref l, ref r, .. } => {
+                stack.push(r);
+                stack.push(l);
+            }
+        }
+    }
+    lints
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Loc;
+
+    #[test]
+    fn test_double_negation() {
+        let lints = |s: &str| lint(&s.parse().unwrap());
+        assert_eq!(
+            lints("1 - -(-2)"),
+            vec![Lint::new(LintKind::DoubleNegation, Loc(4, 7))]
+        );
+        assert_eq!(lints("-1 - -2 + -+-3"), vec![]);
+    }
+}
@@ -0,0 +1,225 @@
+//! Operator table driving the expression parser and the pretty-printer
+
+use super::lexer::TokenKind;
+use super::parser::{BinOpKind, Func, UniOpKind};
+
+/// Highest precedence level of a declared infix operator
+pub const MAX_LEVEL: u8 = 9;
+/// Binding power of implicit multiplication such as `2(3 + 4)`, tighter than
+/// `*` and `/` but looser than the prefix operators
+pub const IMPLICIT_MUL_BP: u8 = 8;
+/// Binding power of the built-in and declared prefix operators
+pub const PREFIX_BP: u8 = 10;
+/// Binding power of declared postfix operators
+pub const POSTFIX_BP: u8 = 11;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Assoc {
+    Left,
+    Right,
+    /// Chaining the operator, as in `a op b op c`, is an error
+    None,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PrefixOp {
+    pub token: TokenKind<'static>,
+    pub kind: UniOpKind,
+    pub bp: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct InfixOp {
+    pub token: TokenKind<'static>,
+    pub kind: BinOpKind,
+    pub bp: u8,
+    pub assoc: Assoc,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PostfixOp {
+    pub token: TokenKind<'static>,
+    pub kind: UniOpKind,
+    pub bp: u8,
+}
+
+impl PrefixOp {
+    /// Minimum binding power an infix or postfix operator needs to be part
+    /// of the operand
+    pub fn right_bp(&self) -> u16 {
+        u16::from(self.bp) * 2 + 1
+    }
+}
+
+impl InfixOp {
+    /// The implicit multiplication of juxtaposed operands, e.g. `2x` or `3 m`
+    pub fn juxtaposition() -> Self {
+        InfixOp {
+            token: TokenKind::Asterisk,
+            kind: BinOpKind::Mul,
+            bp: IMPLICIT_MUL_BP,
+            assoc: Assoc::Left,
+        }
+    }
+
+    /// Binding powers towards the left and the right operand
+    pub fn binding(&self) -> (u16, u16) {
+        let bp = u16::from(self.bp) * 2;
+        match self.assoc {
+            Assoc::Left | Assoc::None => (bp, bp + 1),
+            Assoc::Right => (bp + 1, bp),
+        }
+    }
+}
+
+impl PostfixOp {
+    pub fn left_bp(&self) -> u16 {
+        u16::from(self.bp) * 2
+    }
+}
+
+/// Operators known to the parser, keyed by token
+///
+/// Higher binding powers bind tighter. The built-in `+` and `-` are at level
+/// 6, `*` and `/` at level 7 and the unit conversion `in` at level 0. A token
+/// may be both a prefix operator and an infix or postfix one; a token which is
+/// both infix and postfix is parsed as infix.
+///
+/// ```
+/// use myparse::operator::{Assoc, OperatorTable};
+/// use myparse::lexer::TokenKind;
+/// use myparse::parser::{BinOpKind, ParseConfig};
+///
+/// // make `-` right associative
+/// let mut table = OperatorTable::default();
+/// table.add_infix(TokenKind::Minus, BinOpKind::Sub, 6, Assoc::Right);
+/// let config = ParseConfig::new().table(table);
+/// # let _ = config;
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperatorTable {
+    prefix: Vec<PrefixOp>,
+    infix: Vec<InfixOp>,
+    postfix: Vec<PostfixOp>,
+}
+
+impl Default for OperatorTable {
+    /// The standard grammar:
+    ///
+    /// ```text
+    /// EXPR4 = EXPR3 ("in" EXPR3)?
+    /// EXPR3 = EXPR2 (("+" | "-") EXPR2)*
+    /// EXPR2 = EXPR1 (("*" | "/") EXPR1)*
+    /// EXPR1 = ("+" | "-" | FUNC) EXPR1 | ATOM
+    /// FUNC  = "re" | "im" | "abs" | "arg" | "conj"
+    /// ```
+    fn default() -> Self {
+        let mut table = Self::new();
+        table.add_infix(
+            TokenKind::Ident("in".into()),
+            BinOpKind::Convert,
+            0,
+            Assoc::None,
+        );
+        table.add_infix(TokenKind::Plus, BinOpKind::Add, 6, Assoc::Left);
+        table.add_infix(TokenKind::Minus, BinOpKind::Sub, 6, Assoc::Left);
+        table.add_infix(TokenKind::Asterisk, BinOpKind::Mul, 7, Assoc::Left);
+        table.add_infix(TokenKind::Slash, BinOpKind::Div, 7, Assoc::Left);
+        table.add_prefix(TokenKind::Plus, UniOpKind::Plus, PREFIX_BP);
+        table.add_prefix(TokenKind::Minus, UniOpKind::Minus, PREFIX_BP);
+        for &func in Func::ALL.iter() {
+            table.add_prefix(
+                TokenKind::Ident(func.name().into()),
+                UniOpKind::Func(func),
+                PREFIX_BP,
+            );
+        }
+        table
+    }
+}
+
+impl OperatorTable {
+    /// Create a table without any operator
+    pub fn new() -> Self {
+        OperatorTable {
+            prefix: Vec::new(),
+            infix: Vec::new(),
+            postfix: Vec::new(),
+        }
+    }
+
+    /// Add a prefix operator, replacing the prefix operator of the same token
+    pub fn add_prefix(&mut self, token: TokenKind<'static>, kind: UniOpKind, bp: u8) {
+        self.prefix.retain(|op| op.token != token);
+        self.prefix.push(PrefixOp { token, kind, bp });
+    }
+
+    /// Add an infix operator, replacing the infix operator of the same token
+    pub fn add_infix(&mut self, token: TokenKind<'static>, kind: BinOpKind, bp: u8, assoc: Assoc) {
+        self.infix.retain(|op| op.token != token);
+        self.infix.push(InfixOp {
+            token,
+            kind,
+            bp,
+            assoc,
+        });
+    }
+
+    /// Add a postfix operator, replacing the postfix operator of the same token
+    pub fn add_postfix(&mut self, token: TokenKind<'static>, kind: UniOpKind, bp: u8) {
+        self.postfix.retain(|op| op.token != token);
+        self.postfix.push(PostfixOp { token, kind, bp });
+    }
+
+    /// Remove every operator of `token`
+    pub fn remove(&mut self, token: &TokenKind<'_>) {
+        self.prefix.retain(|op| op.token != *token);
+        self.infix.retain(|op| op.token != *token);
+        self.postfix.retain(|op| op.token != *token);
+    }
+
+    /// Whether `token` is an operator of any fixity
+    pub fn contains(&self, token: &TokenKind<'_>) -> bool {
+        self.prefix(token).is_some() || self.infix(token).is_some() || self.postfix(token).is_some()
+    }
+
+    /// Symbols of the operators of any fixity, e.g. `+` or `<+>`
+    pub fn symbols(&self) -> impl Iterator<Item = &str> {
+        let prefix = self.prefix.iter().map(|op| &op.token);
+        let infix = self.infix.iter().map(|op| &op.token);
+        let postfix = self.postfix.iter().map(|op| &op.token);
+        prefix
+            .chain(infix)
+            .chain(postfix)
+            .filter_map(TokenKind::as_symbol)
+    }
+
+    pub fn prefix(&self, token: &TokenKind<'_>) -> Option<&PrefixOp> {
+        self.prefix.iter().find(|op| op.token == *token)
+    }
+
+    pub fn infix(&self, token: &TokenKind<'_>) -> Option<&InfixOp> {
+        self.infix.iter().find(|op| op.token == *token)
+    }
+
+    pub fn postfix(&self, token: &TokenKind<'_>) -> Option<&PostfixOp> {
+        self.postfix.iter().find(|op| op.token == *token)
+    }
+
+    pub fn prefix_ops(&self) -> impl Iterator<Item = &PrefixOp> {
+        self.prefix.iter()
+    }
+
+    pub fn prefix_by_kind(&self, kind: &UniOpKind) -> Option<&PrefixOp> {
+        self.prefix.iter().find(|op| op.kind == *kind)
+    }
+
+    pub fn infix_by_kind(&self, kind: &BinOpKind) -> Option<&InfixOp> {
+        self.infix.iter().find(|op| op.kind == *kind)
+    }
+
+    pub fn postfix_by_kind(&self, kind: &UniOpKind) -> Option<&PostfixOp> {
+        self.postfix.iter().find(|op| op.kind == *kind)
+    }
+}
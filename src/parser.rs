@@ -1,20 +1,89 @@
+use std::borrow::Cow;
 use std::fmt;
+use std::hash::{Hash, Hasher};
 use std::iter::Peekable;
+use std::mem;
 use std::str::FromStr;
 
-use super::lexer::{Annot, LexError, Lexer, Loc, Token, TokenKind};
+use super::lexer::{Annot, Decimal, LexError, LexErrorKind, Lexer, Loc, Token, TokenKind};
+use super::operator::{Assoc, InfixOp, OperatorTable, MAX_LEVEL, POSTFIX_BP, PREFIX_BP};
+use super::units;
+use super::visit::{Step, Walk};
 
 pub type Result<T> = std::result::Result<T, ParseError>;
 
+/// Kind of token the parser could have accepted
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Expected {
+    Number,
+    Ident,
+    /// Any infix or postfix operator
+    Operator,
+    EndOfInput,
+    /// A line break ending a statement of a program
+    EndOfLine,
+    Token(TokenKind<'static>),
+}
+
+impl fmt::Display for Expected {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Expected::*;
+        match self {
+            Number => write!(f, "number"),
+            Ident => write!(f, "identifier"),
+            Operator => write!(f, "operator"),
+            EndOfInput => write!(f, "end of input"),
+            EndOfLine => write!(f, "end of line"),
+            Token(token) => write!(f, "`{}`", token),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ParseError {
-    #[allow(dead_code)]
-    UnexpectedToken(Token),
-    NotExpression(Token),
-    NotOperator(Token),
-    UnclosedOpenParen(Token),
-    RedundantExpression(Token),
-    Eof,
+    /// A token other than the ones the parser could accept
+    ExpectedToken {
+        expected: Vec<Expected>,
+        /// `None` at the end of the input
+        found: Option<Token<'static>>,
+        /// The `(` enclosing the position, if it is not closed yet
+        opener: Option<Token<'static>>,
+    },
+    /// A non-associative operator chained with an operator of the same binding power
+    NonAssociative(Token<'static>),
+    /// Declaration of a built-in operator
+    BuiltinOperator(Token<'static>),
+    /// Declaration of an operator already declared with an incompatible fixity
+    OperatorConflict(Token<'static>),
+    /// Precedence level of a declaration out of `0..=9`
+    InvalidLevel(Token<'static>),
+    /// Nesting of parentheses and operands deeper than the configured limit
+    TooDeep { token: Token<'static>, limit: usize },
+}
+
+impl ParseError {
+    fn expected(expected: Vec<Expected>, found: Option<Token<'_>>) -> Self {
+        ParseError::ExpectedToken {
+            expected,
+            found: found.map(Token::into_owned),
+            opener: None,
+        }
+    }
+
+    /// Span of the offending token, `None` at the end of the input
+    pub fn loc(&self) -> Option<&Loc> {
+        use self::ParseError::*;
+        match self {
+            ExpectedToken { found, .. } => found.as_ref().map(|token| &token.loc),
+            NonAssociative(token)
+            | BuiltinOperator(token)
+            | OperatorConflict(token)
+            | InvalidLevel(token)
+            | TooDeep { token, .. } => Some(&token.loc),
+        }
+    }
 }
 
 impl std::error::Error for ParseError {}
@@ -23,62 +92,154 @@ impl fmt::Display for ParseError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use self::ParseError::*;
         match self {
-            UnexpectedToken(token) => write!(f, "{}: {} is not expected", token.loc, token.value),
-            NotExpression(token) => write!(
+            ExpectedToken {
+                expected, found, ..
+            } => {
+                if let Some(token) = found {
+                    write!(f, "{}: ", token.loc)?;
+                }
+                write!(f, "expected ")?;
+                for (i, e) in expected.iter().enumerate() {
+                    match i {
+                        0 => {}
+                        _ if i + 1 == expected.len() => write!(f, " or ")?,
+                        _ => write!(f, ", ")?,
+                    }
+                    write!(f, "{}", e)?;
+                }
+                match found {
+                    Some(token) => write!(f, ", found `{}`", token.value),
+                    None => write!(f, ", found end of input"),
+                }
+            }
+            NonAssociative(token) => write!(
+                f,
+                "{}: '{}' is not associative; add parentheses",
+                token.loc, token.value
+            ),
+            BuiltinOperator(token) => write!(
                 f,
-                "{}: '{}' is not a start of expression",
+                "{}: '{}' is a built-in operator and cannot be declared",
                 token.loc, token.value
             ),
-            NotOperator(token) => write!(f, "{}: '{}' is not an operator", token.loc, token.value),
-            UnclosedOpenParen(token) => write!(f, "{}: '{}' is not closed", token.loc, token.value),
-            RedundantExpression(token) => write!(
+            OperatorConflict(token) => write!(
                 f,
-                "{}: expression after '{}' is redundant",
+                "{}: '{}' is already declared with a conflicting fixity",
                 token.loc, token.value
             ),
-            Eof => write!(f, "End of file"),
+            InvalidLevel(token) => write!(
+                f,
+                "{}: '{}' is not a precedence level between 0 and {}",
+                token.loc, token.value, MAX_LEVEL
+            ),
+            TooDeep { token, limit } => write!(
+                f,
+                "{}: expression is nested deeper than {} levels",
+                token.loc, limit
+            ),
         }
     }
 }
 
+/// Print the line of `input` containing `loc` with the span underlined
 pub fn print_annot(input: &str, loc: &Loc) {
-    eprintln!("{}", input);
-    eprintln!("{}{}", " ".repeat(loc.0), "^".repeat(loc.1 - loc.0));
+    print_annots(input, loc, None)
+}
+
+/// Same as `print_annot`, also underlining the `secondary` span with `-`
+pub fn print_annots(input: &str, loc: &Loc, secondary: Option<&Loc>) {
+    let line = line_range(input, loc.0);
+    match secondary {
+        Some(secondary) if line_range(input, secondary.0) != line => {
+            print_marked(input, line_range(input, secondary.0), &[(secondary, '-')]);
+            print_marked(input, line, &[(loc, '^')]);
+        }
+        Some(secondary) => print_marked(input, line, &[(secondary, '-'), (loc, '^')]),
+        None => print_marked(input, line, &[(loc, '^')]),
+    }
+}
+
+/// Line and column of `pos` in `input`, both starting at 1
+pub fn line_col(input: &str, pos: usize) -> (usize, usize) {
+    let before = &input[..pos.min(input.len())];
+    let start = line_range(input, pos).0;
+    let line = before.matches('\n').count() + 1;
+    (line, before[start..].chars().count() + 1)
+}
+
+/// Start and end of the line of `input` containing `pos`
+fn line_range(input: &str, pos: usize) -> (usize, usize) {
+    let start = input[..pos.min(input.len())]
+        .rfind('\n')
+        .map_or(0, |i| i + 1);
+    let end = input[start..].find('\n').map_or(input.len(), |i| start + i);
+    (start, end)
+}
+
+fn print_marked(input: &str, (start, end): (usize, usize), marks: &[(&Loc, char)]) {
+    let mut line = String::new();
+    for (loc, c) in marks {
+        let from = loc.0 - start;
+        let to = loc.1.min(end).max(loc.0 + 1) - start;
+        if line.len() < to {
+            line.push_str(&" ".repeat(to - line.len()));
+        }
+        line.replace_range(from..to, &c.to_string().repeat(to - from));
+    }
+    eprintln!("{}", &input[start..end]);
+    eprintln!("{}", line.trim_end());
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Error {
     Lexer(LexError),
     Parser(ParseError),
 }
 
 impl Error {
+    /// Whether the input ended in the middle of an expression or a comment,
+    /// so that more input could complete it
+    pub fn is_incomplete(&self) -> bool {
+        match self {
+            Error::Lexer(err) => matches!(
+                err.value,
+                LexErrorKind::Eof | LexErrorKind::UnterminatedComment
+            ),
+            Error::Parser(err) => matches!(err, ParseError::ExpectedToken { found: None, .. }),
+        }
+    }
+
+    /// Span of the error, `None` at the end of the input
+    pub fn loc(&self) -> Option<&Loc> {
+        match self {
+            Error::Lexer(err) => Some(&err.loc),
+            Error::Parser(err) => err.loc(),
+        }
+    }
+
     pub fn show_diagnostic(&self, input: &str) {
-        use self::Error::*;
-        use self::ParseError as P;
-        let temp_loc;
-        let (err, loc): (&std::error::Error, &Loc) = match self {
-            Lexer(err) => (err, &err.loc),
-            Parser(err) => (
-                err,
+        match self {
+            Error::Lexer(err) => {
+                eprintln!("{}", err);
+                print_annot(input, &err.loc);
+            }
+            Error::Parser(err) => {
+                eprintln!("{}", err);
+                let eof = Loc(input.len(), input.len() + 1);
+                let loc = err.loc().unwrap_or(&eof);
                 match err {
-                    P::UnexpectedToken(Token { loc, .. })
-                    | P::NotExpression(Token { loc, .. })
-                    | P::NotOperator(Token { loc, .. })
-                    | P::UnclosedOpenParen(Token { loc, .. }) => loc,
-                    P::RedundantExpression(Token { loc, .. }) => {
-                        temp_loc = Loc(loc.0, input.len());
-                        &temp_loc
-                    }
-                    P::Eof => {
-                        temp_loc = Loc(input.len(), input.len() + 1);
-                        &temp_loc
+                    ParseError::ExpectedToken {
+                        opener: Some(opener),
+                        ..
+                    } => {
+                        print_annots(input, loc, Some(&opener.loc));
+                        eprintln!("note: {}: unclosed `{}`", opener.loc, opener.value);
                     }
-                },
-            ),
-        };
-        eprintln!("{}", err);
-        print_annot(input, loc);
+                    _ => print_annot(input, loc),
+                }
+            }
+        }
     }
 }
 
@@ -110,24 +271,326 @@ impl fmt::Display for Error {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// With the `serde` feature, serialized as the flat list of its nodes in
+/// postorder
+#[derive(Debug, Eq)]
 pub enum AstNode {
     Num(u64),
-    UniOp { op: UniOp, e: Box<Ast> },
-    BinOp { op: BinOp, l: Box<Ast>, r: Box<Ast> },
+    Decimal(Decimal),
+    /// Imaginary literal, e.g. `2.5i`
+    Imaginary(Decimal),
+    /// Parameter of a declared operator, previous result or unit
+    Var(String),
+    UniOp {
+        op: UniOp,
+        e: Box<Ast>,
+    },
+    BinOp {
+        op: BinOp,
+        l: Box<Ast>,
+        r: Box<Ast>,
+    },
 }
 
 pub type Ast = Annot<AstNode>;
 
+impl Drop for AstNode {
+    /// Drop the subtrees with an explicit stack, so that deep trees do not
+    /// exhaust the call stack
+    fn drop(&mut self) {
+        fn detach(node: &mut AstNode, stack: &mut Vec<AstNode>) {
+            let (first, second) = match node {
+                AstNode::UniOp { e, .. } => (e, None),
+                AstNode::BinOp { l, r, .. } => (l, Some(r)),
+                AstNode::Num(_) | AstNode::Decimal(_) | AstNode::Imaginary(_) | AstNode::Var(_) => {
+                    return
+                }
+            };
+            for child in Some(first).into_iter().chain(second) {
+                if let AstNode::UniOp { .. } | AstNode::BinOp { .. } = child.value {
+                    stack.push(mem::replace(&mut child.value, AstNode::Num(0)));
+                }
+            }
+        }
+
+        let mut stack = Vec::new();
+        detach(self, &mut stack);
+        while let Some(mut node) = stack.pop() {
+            detach(&mut node, &mut stack);
+        }
+    }
+}
+
+impl Clone for AstNode {
+    /// Clone the subtrees bottom-up with an explicit stack, like `drop`
+    fn clone(&self) -> Self {
+        fn clone_tree(ast: &Ast) -> Ast {
+            let mut clones = Vec::new();
+            for step in Walk::new(ast) {
+                let ast = match step {
+                    Step::Enter(_) => continue,
+                    Step::Leave(ast) => ast,
+                };
+                let value = match ast.value {
+                    AstNode::UniOp { ref op, .. } => AstNode::UniOp {
+                        op: op.clone(),
+                        e: Box::new(clones.pop().unwrap()),
+                    },
+                    AstNode::BinOp { ref op, .. } => {
+                        let r = clones.pop().unwrap();
+                        let l = clones.pop().unwrap();
+                        AstNode::BinOp {
+                            op: op.clone(),
+                            l: Box::new(l),
+                            r: Box::new(r),
+                        }
+                    }
+                    ref leaf => leaf.clone(),
+                };
+                clones.push(Ast::new(value, ast.loc.clone()));
+            }
+            clones.pop().unwrap()
+        }
+
+        match self {
+            AstNode::Num(n) => AstNode::Num(*n),
+            AstNode::Decimal(d) => AstNode::Decimal(*d),
+            AstNode::Imaginary(d) => AstNode::Imaginary(*d),
+            AstNode::Var(name) => AstNode::Var(name.clone()),
+            AstNode::UniOp { op, e } => AstNode::UniOp {
+                op: op.clone(),
+                e: Box::new(clone_tree(e)),
+            },
+            AstNode::BinOp { op, l, r } => AstNode::BinOp {
+                op: op.clone(),
+                l: Box::new(clone_tree(l)),
+                r: Box::new(clone_tree(r)),
+            },
+        }
+    }
+}
+
+impl PartialEq for AstNode {
+    /// Compare the subtrees pairwise with an explicit stack, like `clone`
+    fn eq(&self, other: &Self) -> bool {
+        use self::AstNode::*;
+        let mut stack = vec![(self, other)];
+        while let Some(pair) = stack.pop() {
+            match pair {
+                (Num(a), Num(b)) if a == b => {}
+                (Decimal(a), Decimal(b)) | (Imaginary(a), Imaginary(b)) if a == b => {}
+                (Var(a), Var(b)) if a == b => {}
+                (UniOp { op: op_a, e: a }, UniOp { op: op_b, e: b })
+                    if op_a == op_b && a.loc == b.loc =>
+                {
+                    stack.push((&a.value, &b.value));
+                }
+                (
+                    BinOp {
+                        op: op_a,
+                        l: l_a,
+                        r: r_a,
+                    },
+                    BinOp {
+                        op: op_b,
+                        l: l_b,
+                        r: r_b,
+                    },
+                ) if op_a == op_b && l_a.loc == l_b.loc && r_a.loc == r_b.loc => {
+                    stack.push((&r_a.value, &r_b.value));
+                    stack.push((&l_a.value, &l_b.value));
+                }
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+impl Hash for AstNode {
+    /// Hash the nodes with an explicit stack, like `eq`
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        use self::AstNode::*;
+        let mut stack = vec![self];
+        while let Some(node) = stack.pop() {
+            mem::discriminant(node).hash(state);
+            match node {
+                Num(n) => n.hash(state),
+                Decimal(d) | Imaginary(d) => d.hash(state),
+                Var(name) => name.hash(state),
+                UniOp { op, e } => {
+                    op.hash(state);
+                    e.loc.hash(state);
+                    stack.push(&e.value);
+                }
+                BinOp { op, l, r } => {
+                    op.hash(state);
+                    l.loc.hash(state);
+                    r.loc.hash(state);
+                    stack.push(&r.value);
+                    stack.push(&l.value);
+                }
+            }
+        }
+    }
+}
+
+/// `AstNode` is serialized as the list of its nodes in postorder instead of
+/// nested values, so that trees of any depth can be serialized and
+/// deserialized without recursion. The location of every node but the root,
+/// which is held by the enclosing `Ast`, is stored in its parent.
+#[cfg(feature = "serde")]
+mod serde_ast {
+    use serde::de::{self, Deserializer, SeqAccess, Visitor};
+    use serde::ser::{SerializeSeq, Serializer};
+    use serde::{Deserialize, Serialize};
+    use std::fmt;
+
+    use super::{Ast, AstNode, BinOp, Decimal, Loc, UniOp};
+
+    #[derive(Serialize)]
+    #[serde(rename = "Node")]
+    enum NodeRef<'a> {
+        Num(u64),
+        Decimal(Decimal),
+        Imaginary(Decimal),
+        Var(&'a str),
+        UniOp {
+            op: &'a UniOp,
+            e: &'a Loc,
+        },
+        BinOp {
+            op: &'a BinOp,
+            l: &'a Loc,
+            r: &'a Loc,
+        },
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename = "Node")]
+    enum Node {
+        Num(u64),
+        Decimal(Decimal),
+        Imaginary(Decimal),
+        Var(String),
+        UniOp { op: UniOp, e: Loc },
+        BinOp { op: BinOp, l: Loc, r: Loc },
+    }
+
+    impl Serialize for AstNode {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            // preorder with the right child first, the reverse of postorder
+            let mut nodes = Vec::new();
+            let mut stack = vec![self];
+            while let Some(node) = stack.pop() {
+                nodes.push(node);
+                match node {
+                    AstNode::UniOp { e, .. } => stack.push(&e.value),
+                    AstNode::BinOp { l, r, .. } => {
+                        stack.push(&l.value);
+                        stack.push(&r.value);
+                    }
+                    AstNode::Num(_)
+                    | AstNode::Decimal(_)
+                    | AstNode::Imaginary(_)
+                    | AstNode::Var(_) => {}
+                }
+            }
+
+            let mut seq = serializer.serialize_seq(Some(nodes.len()))?;
+            for node in nodes.into_iter().rev() {
+                seq.serialize_element(&match node {
+                    AstNode::Num(n) => NodeRef::Num(*n),
+                    AstNode::Decimal(d) => NodeRef::Decimal(*d),
+                    AstNode::Imaginary(d) => NodeRef::Imaginary(*d),
+                    AstNode::Var(name) => NodeRef::Var(name),
+                    AstNode::UniOp { op, e } => NodeRef::UniOp { op, e: &e.loc },
+                    AstNode::BinOp { op, l, r } => NodeRef::BinOp {
+                        op,
+                        l: &l.loc,
+                        r: &r.loc,
+                    },
+                })?;
+            }
+            seq.end()
+        }
+    }
+
+    impl<'de> Deserialize<'de> for AstNode {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_seq(NodesVisitor)
+        }
+    }
+
+    struct NodesVisitor;
+
+    impl<'de> Visitor<'de> for NodesVisitor {
+        type Value = AstNode;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a list of syntax tree nodes in postorder")
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<AstNode, A::Error> {
+            let missing = || de::Error::custom("operator without operands in the node list");
+            let mut operands = Vec::new();
+            while let Some(node) = seq.next_element()? {
+                let node = match node {
+                    Node::Num(n) => AstNode::Num(n),
+                    Node::Decimal(d) => AstNode::Decimal(d),
+                    Node::Imaginary(d) => AstNode::Imaginary(d),
+                    Node::Var(name) => AstNode::Var(name),
+                    Node::UniOp { op, e: loc } => {
+                        let e = operands.pop().ok_or_else(missing)?;
+                        AstNode::UniOp {
+                            op,
+                            e: Box::new(Ast::new(e, loc)),
+                        }
+                    }
+                    Node::BinOp {
+                        op,
+                        l: l_loc,
+                        r: r_loc,
+                    } => {
+                        let (r, l) = match (operands.pop(), operands.pop()) {
+                            (Some(r), Some(l)) => (r, l),
+                            _ => return Err(missing()),
+                        };
+                        AstNode::BinOp {
+                            op,
+                            l: Box::new(Ast::new(l, l_loc)),
+                            r: Box::new(Ast::new(r, r_loc)),
+                        }
+                    }
+                };
+                operands.push(node);
+            }
+            match (operands.pop(), operands.is_empty()) {
+                (Some(node), true) => Ok(node),
+                _ => Err(de::Error::custom("node list does not form a single tree")),
+            }
+        }
+    }
+}
+
 impl Ast {
-    fn num(n: u64, loc: Loc) -> Self {
+    pub fn num(n: u64, loc: Loc) -> Self {
         // call Annot::new
         Self::new(AstNode::Num(n), loc)
     }
-    fn uniop(op: UniOp, e: Ast, loc: Loc) -> Self {
+    pub fn decimal(digits: u64, scale: u32, loc: Loc) -> Self {
+        Self::new(AstNode::Decimal(Decimal { digits, scale }), loc)
+    }
+    pub fn imaginary(digits: u64, scale: u32, loc: Loc) -> Self {
+        Self::new(AstNode::Imaginary(Decimal { digits, scale }), loc)
+    }
+    pub fn var(name: &str, loc: Loc) -> Self {
+        Self::new(AstNode::Var(name.to_string()), loc)
+    }
+    pub fn uniop(op: UniOp, e: Ast, loc: Loc) -> Self {
         Self::new(AstNode::UniOp { op, e: Box::new(e) }, loc)
     }
-    fn binop(op: BinOp, l: Ast, r: Ast, loc: Loc) -> Self {
+    pub fn binop(op: BinOp, l: Ast, r: Ast, loc: Loc) -> Self {
         Self::new(
             AstNode::BinOp {
                 op,
@@ -142,244 +605,909 @@ impl Ast {
 impl FromStr for Ast {
     type Err = Error;
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        let tokens = Lexer::new(s).lex()?;
-        let ast = parse(tokens)?;
-        Ok(ast)
+        parse_tokens(Lexer::new(s), None)
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UniOpKind {
     Plus,
     Minus,
+    /// Built-in function, e.g. `re` in `re(z)`
+    Func(Func),
+    /// Declared operator, named by its symbol
+    Custom(String),
+}
+
+/// Built-in functions of complex numbers, parsed as prefix operators
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Func {
+    /// Real part
+    Re,
+    /// Imaginary part
+    Im,
+    /// Absolute value, or modulus
+    Abs,
+    /// Argument, in radians
+    Arg,
+    /// Complex conjugate
+    Conj,
+}
+
+impl Func {
+    pub const ALL: [Func; 5] = [Func::Re, Func::Im, Func::Abs, Func::Arg, Func::Conj];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Func::Re => "re",
+            Func::Im => "im",
+            Func::Abs => "abs",
+            Func::Arg => "arg",
+            Func::Conj => "conj",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Func::ALL.iter().copied().find(|func| func.name() == name)
+    }
 }
 
 pub type UniOp = Annot<UniOpKind>;
 
 impl UniOp {
-    fn plus(loc: Loc) -> Self {
+    pub fn plus(loc: Loc) -> Self {
         Self::new(UniOpKind::Plus, loc)
     }
-    fn minus(loc: Loc) -> Self {
+    pub fn minus(loc: Loc) -> Self {
         Self::new(UniOpKind::Minus, loc)
     }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinOpKind {
     Add,
     Sub,
     Mul,
     Div,
+    /// Conversion of the left operand to the unit on the right, `x in km`
+    Convert,
+    /// Declared operator, named by its symbol
+    Custom(String),
 }
 
 pub type BinOp = Annot<BinOpKind>;
 
 impl BinOp {
-    fn add(loc: Loc) -> Self {
+    pub fn add(loc: Loc) -> Self {
         Self::new(BinOpKind::Add, loc)
     }
-    fn sub(loc: Loc) -> Self {
+    pub fn sub(loc: Loc) -> Self {
         Self::new(BinOpKind::Sub, loc)
     }
-    fn mul(loc: Loc) -> Self {
+    pub fn mul(loc: Loc) -> Self {
         Self::new(BinOpKind::Mul, loc)
     }
-    fn div(loc: Loc) -> Self {
+    pub fn div(loc: Loc) -> Self {
         Self::new(BinOpKind::Div, loc)
     }
 }
 
-fn parse(tokens: Vec<Token>) -> Result<Ast> {
-    let mut tokens = tokens.into_iter().peekable();
-    let ret = parse_expr(&mut tokens)?;
-    let ret = match tokens.next() {
-        Some(token) => Err(ParseError::RedundantExpression(token)),
-        None => Ok(ret),
-    };
-    // eprintln!("parse result: {:?}", ret);
-    ret
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Fixity {
+    Prefix,
+    Infix(Assoc),
+    Postfix,
+}
+
+impl Fixity {
+    /// Fixity introduced by the declaration keyword `s`
+    pub fn from_keyword(s: &str) -> Option<Self> {
+        match s {
+            "prefix" => Some(Fixity::Prefix),
+            "infixl" => Some(Fixity::Infix(Assoc::Left)),
+            "infixr" => Some(Fixity::Infix(Assoc::Right)),
+            "infix" => Some(Fixity::Infix(Assoc::None)),
+            "postfix" => Some(Fixity::Postfix),
+            _ => None,
+        }
+    }
+
+    /// Declaration keyword introducing this fixity
+    pub fn keyword(self) -> &'static str {
+        match self {
+            Fixity::Prefix => "prefix",
+            Fixity::Infix(Assoc::Left) => "infixl",
+            Fixity::Infix(Assoc::Right) => "infixr",
+            Fixity::Infix(Assoc::None) => "infix",
+            Fixity::Postfix => "postfix",
+        }
+    }
+}
+
+/// Operator declaration such as `infixl 6 <+> (a, b) = a + 2 * b`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OpDecl {
+    pub fixity: Fixity,
+    pub bp: u8,
+    pub symbol: Token<'static>,
+    pub params: Vec<String>,
+    pub body: Ast,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Stmt {
+    Expr(Ast),
+    OpDecl(OpDecl),
+}
+
+/// Statements run in order
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Program {
+    pub stmts: Vec<Stmt>,
+    /// Span of every statement, from its first token to its last one
+    pub locs: Vec<Loc>,
+}
+
+impl FromStr for Program {
+    type Err = Error;
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        parse_program(s, &ParseConfig::default())
+    }
+}
+
+/// Hook receiving the rules the parser enters and leaves
+pub trait Tracer {
+    /// Called before `rule` is tried, with the next token in the input
+    fn enter(&mut self, rule: &'static str, next: Option<&Token<'_>>);
+    /// Called when `rule` is done, with the span it parsed on success
+    fn exit(&mut self, rule: &'static str, result: std::result::Result<&Loc, &ParseError>);
+}
+
+/// Default of `ParseConfig::max_depth`
+pub const DEFAULT_MAX_DEPTH: usize = 256;
+
+/// Parser settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseConfig {
+    table: OperatorTable,
+    implicit_mul: bool,
+    max_depth: usize,
+}
+
+impl Default for ParseConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ParseConfig {
+    pub fn new() -> Self {
+        ParseConfig {
+            table: OperatorTable::default(),
+            implicit_mul: false,
+            max_depth: DEFAULT_MAX_DEPTH,
+        }
+    }
+
+    /// Set the maximum nesting of parentheses, prefix operators and right
+    /// operands, beyond which `ParseError::TooDeep` is reported instead of
+    /// exhausting the stack
+    ///
+    /// Left-associative chains such as `1 + 2 + 3` do not nest.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Set the operators to parse
+    pub fn table(mut self, table: OperatorTable) -> Self {
+        self.table = table;
+        self
+    }
+
+    pub fn operators(&self) -> &OperatorTable {
+        &self.table
+    }
+
+    /// Parse juxtaposed operands such as `2(3 + 4)`, `(1 + 2)(3 + 4)` or `3x`
+    /// as a multiplication
+    ///
+    /// The inserted `Mul` node has a zero-width operator location at the start
+    /// of the right operand and binds at `IMPLICIT_MUL_BP`, so that `1 / 2x`
+    /// is `1 / (2 * x)` while `-2x` is `(-2) * x`. Off by default, except
+    /// for a number literal followed by the name of a unit, such as the
+    /// quantity `3 m`.
+    pub fn implicit_mul(mut self, implicit_mul: bool) -> Self {
+        self.implicit_mul = implicit_mul;
+        self
+    }
+
+    /// Add the operator of `decl` to the table
+    ///
+    /// Built-in operators cannot be declared, and a symbol cannot be both a
+    /// postfix operator and a prefix or infix one. Declaring an operator again
+    /// with the same fixity replaces it.
+    pub fn declare(&mut self, decl: &OpDecl) -> Result<()> {
+        let token = &decl.symbol.value;
+        if OperatorTable::default().contains(token) || *token == TokenKind::symbol("=") {
+            return Err(ParseError::BuiltinOperator(decl.symbol.clone()));
+        }
+        let table = &self.table;
+        let conflict = match decl.fixity {
+            Fixity::Prefix | Fixity::Infix(_) => table.postfix(token).is_some(),
+            Fixity::Postfix => table.prefix(token).is_some() || table.infix(token).is_some(),
+        };
+        if conflict {
+            return Err(ParseError::OperatorConflict(decl.symbol.clone()));
+        }
+
+        let symbol = token.to_string();
+        match decl.fixity {
+            Fixity::Prefix => {
+                self.table
+                    .add_prefix(token.clone(), UniOpKind::Custom(symbol), decl.bp)
+            }
+            Fixity::Infix(assoc) => {
+                self.table
+                    .add_infix(token.clone(), BinOpKind::Custom(symbol), decl.bp, assoc)
+            }
+            Fixity::Postfix => {
+                self.table
+                    .add_postfix(token.clone(), UniOpKind::Custom(symbol), decl.bp)
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn parse(tokens: Vec<Token<'_>>) -> Result<Ast> {
+    Parser::new(tokens.into_iter(), &ParseConfig::default(), None).parse()
 }
 
-/// Parse EXPR
+/// Parse the tokens as they are produced, e.g. by a `Lexer`
 ///
-/// EXPR = EXPR3
-fn parse_expr<T>(tokens: &mut Peekable<T>) -> Result<Ast>
+/// A lex error is reported in place of the parse result as soon as the parser
+/// has reached it.
+pub fn parse_tokens<'a, I>(
+    tokens: I,
+    tracer: Option<&mut dyn Tracer>,
+) -> std::result::Result<Ast, Error>
 where
-    T: Iterator<Item = Token>,
+    I: IntoIterator<Item = std::result::Result<Token<'a>, LexError>>,
 {
-    // eprintln!("EXPR --");
-    let ret = parse_expr3(tokens);
-    // eprintln!("EXPR: {:?}", ret);
-    ret
+    parse_tokens_with(tokens, &ParseConfig::default(), tracer)
 }
 
-/// Parse binpop
-///
-/// expr = subexpr expr_Loop
-/// expr_Loop = op subexpr expr_Loop | eps
-fn parse_left_binop<T>(
-    tokens: &mut Peekable<T>,
-    subexpr_parser: fn(&mut Peekable<T>) -> Result<Ast>,
-    op_parser: fn(&mut Peekable<T>) -> Result<BinOp>,
-) -> Result<Ast>
+/// Same as `parse_tokens` with custom settings
+pub fn parse_tokens_with<'a, I>(
+    tokens: I,
+    config: &ParseConfig,
+    tracer: Option<&mut dyn Tracer>,
+) -> std::result::Result<Ast, Error>
 where
-    T: Iterator<Item = Token>,
+    I: IntoIterator<Item = std::result::Result<Token<'a>, LexError>>,
 {
-    // subexpr
-    let mut e = subexpr_parser(tokens)?;
-
-    // expr_loop
-    loop {
-        match tokens.peek() {
-            Some(_) => {
-                // op
-                let op = match op_parser(tokens) {
-                    Ok(op) => op,
-                    // no binops any more
-                    Err(_) => break,
-                };
-                // subexpr
-                let r = subexpr_parser(tokens)?;
-                let loc = e.loc.merge(&r.loc);
-                e = Ast::binop(op, e, r, loc);
-            }
-            // eps
-            _ => break,
-        }
-    }
-
-    Ok(e)
+    with_tokens(tokens, config, tracer, |p| p.parse())
 }
 
-fn parse_expr3_op<T>(tokens: &mut Peekable<T>) -> Result<BinOp>
+/// Parse a statement, i.e. an expression or an operator declaration
+pub fn parse_stmt_with<'a, I>(
+    tokens: I,
+    config: &ParseConfig,
+    tracer: Option<&mut dyn Tracer>,
+) -> std::result::Result<Stmt, Error>
 where
-    T: Iterator<Item = Token>,
+    I: IntoIterator<Item = std::result::Result<Token<'a>, LexError>>,
 {
-    // eprintln!("expr3_op --");
-    let token = match tokens.peek() {
-        None => return Err(ParseError::Eof),
-        Some(token) => token,
+    with_tokens(tokens, config, tracer, |p| p.parse_stmt())
+}
+
+/// Parse a program, i.e. statements separated by `;` or line breaks
+///
+/// A line break inside parentheses or after an unfinished expression, e.g.
+/// after an infix operator, does not end the statement. The operators
+/// declared by a statement can be used in the following ones.
+pub fn parse_program(src: &str, config: &ParseConfig) -> std::result::Result<Program, Error> {
+    let mut config = config.clone();
+    let mut lexed = UntilLexError {
+        tokens: Lexer::new(src),
+        err: None,
     };
-    let ret = match token.value {
-        TokenKind::Plus => Ok(BinOp::add(tokens.next().unwrap().loc)),
-        TokenKind::Minus => Ok(BinOp::sub(tokens.next().unwrap().loc)),
-        _ => Err(ParseError::NotOperator(token.clone())),
+    let mut tokens = (&mut lexed).peekable();
+    let mut stmts = Vec::new();
+    let mut locs = Vec::new();
+    let ret = loop {
+        // empty statements
+        while tokens
+            .next_if(|token| token.value == TokenKind::Semicolon)
+            .is_some()
+        {}
+        let start = match tokens.peek() {
+            Some(token) => token.loc.0,
+            None => break Ok(()),
+        };
+        // a parser per statement, as it borrows the table the statement
+        // may add to
+        let mut parser = Parser::from_peekable(tokens, &config, None);
+        parser.src = Some(src);
+        let stmt = parser.parse_stmt();
+        locs.push(Loc(start, parser.last_end));
+        tokens = parser.tokens;
+        match stmt {
+            Ok(Stmt::OpDecl(decl)) => match config.declare(&decl) {
+                Ok(()) => stmts.push(Stmt::OpDecl(decl)),
+                Err(err) => break Err(err),
+            },
+            Ok(stmt) => stmts.push(stmt),
+            Err(err) => break Err(err),
+        }
     };
-    // eprintln!("expr3_op: {:?}", ret);
-    ret
+    match lexed.err {
+        Some(err) => Err(Error::Lexer(err)),
+        None => Ok(ret.map(|()| Program { stmts, locs })?),
+    }
 }
 
-/// Parse EXPR3
-///
-/// EXPR3 = EXPR2 EXPR3_Loop
-/// EXPR3_Loop = ("+" | "-") EXPR2 EXPR3_Loop | eps
-fn parse_expr3<T>(tokens: &mut Peekable<T>) -> Result<Ast>
+fn with_tokens<'a, I, U>(
+    tokens: I,
+    config: &ParseConfig,
+    tracer: Option<&mut dyn Tracer>,
+    f: impl FnOnce(&mut Parser<'_, '_, 'a, &mut UntilLexError<I::IntoIter>>) -> Result<U>,
+) -> std::result::Result<U, Error>
 where
-    T: Iterator<Item = Token>,
+    I: IntoIterator<Item = std::result::Result<Token<'a>, LexError>>,
 {
-    // eprintln!("EXPR3 --");
-    let ret = parse_left_binop(tokens, parse_expr2, parse_expr3_op);
-    // eprintln!("EXPR3: {:?}", ret);
-    ret
+    let mut tokens = UntilLexError {
+        tokens: tokens.into_iter(),
+        err: None,
+    };
+    let ret = f(&mut Parser::new(&mut tokens, config, tracer));
+    match tokens.err {
+        Some(err) => Err(Error::Lexer(err)),
+        None => Ok(ret?),
+    }
+}
+
+/// Yields tokens until the first lex error, which is kept aside
+struct UntilLexError<I> {
+    tokens: I,
+    err: Option<LexError>,
 }
 
-fn parse_expr2_op<T>(tokens: &mut Peekable<T>) -> Result<BinOp>
+impl<'a, I> Iterator for UntilLexError<I>
 where
-    T: Iterator<Item = Token>,
+    I: Iterator<Item = std::result::Result<Token<'a>, LexError>>,
 {
-    // eprintln!("expr2_op --");
-    let token = match tokens.peek() {
-        None => return Err(ParseError::Eof),
-        Some(token) => token,
-    };
-    let ret = match token.value {
-        TokenKind::Asterisk => Ok(BinOp::mul(tokens.next().unwrap().loc)),
-        TokenKind::Slash => Ok(BinOp::div(tokens.next().unwrap().loc)),
-        _ => Err(ParseError::NotOperator(token.clone())),
-    };
-    // eprintln!("expr2_op: {:?}", ret);
-    ret
+    type Item = Token<'a>;
+
+    fn next(&mut self) -> Option<Token<'a>> {
+        if self.err.is_some() {
+            return None;
+        }
+        match self.tokens.next()? {
+            Ok(token) => Some(token),
+            Err(err) => {
+                self.err = Some(err);
+                None
+            }
+        }
+    }
 }
 
-/// Parse EXPR2
-///
-/// EXPR2 = EXPR1 EXPR2_Loop
-/// EXPR2_Loop = ("*" | "/") EXPR1 EXPR2_Loop | eps
-fn parse_expr2<T>(tokens: &mut Peekable<T>) -> Result<Ast>
+struct Parser<'c, 't, 'a, T>
 where
-    T: Iterator<Item = Token>,
+    T: Iterator<Item = Token<'a>>,
 {
-    // eprintln!("EXPR2 --");
-    let ret = parse_left_binop(tokens, parse_expr1, parse_expr2_op);
-    // eprintln!("EXPR2: {:?}", ret);
-    ret
+    tokens: Peekable<T>,
+    /// Remaining parts of a split operator symbol, in reverse order
+    split: Vec<Token<'a>>,
+    table: &'c OperatorTable,
+    implicit_mul: bool,
+    depth: usize,
+    max_depth: usize,
+    tracer: Option<&'t mut dyn Tracer>,
+    /// Source of a program, whose statements end at line breaks
+    src: Option<&'c str>,
+    /// Number of open parentheses
+    parens: usize,
+    /// End of the last token consumed by `bump`
+    last_end: usize,
+    /// Whether the last token consumed by `bump` is a number literal
+    after_number: bool,
 }
 
-/// Parse EXPR1
-///
-/// EXPR1 = ("+" | "-"), ATOM | ATOM
-fn parse_expr1<T>(tokens: &mut Peekable<T>) -> Result<Ast>
+impl<'c, 't, 'a, T> Parser<'c, 't, 'a, T>
 where
-    T: Iterator<Item = Token>,
+    T: Iterator<Item = Token<'a>>,
 {
-    // eprintln!("EXPR1 --");
-    let ret = match tokens.peek().map(|token| token.value) {
-        Some(TokenKind::Plus) | Some(TokenKind::Minus) => {
-            // ("+" | "-")
-            let op = match tokens.next() {
-                Some(Token {
-                    value: TokenKind::Plus,
-                    loc,
-                }) => UniOp::plus(loc),
+    fn new(tokens: T, config: &'c ParseConfig, tracer: Option<&'t mut dyn Tracer>) -> Self {
+        Self::from_peekable(tokens.peekable(), config, tracer)
+    }
+
+    fn from_peekable(
+        tokens: Peekable<T>,
+        config: &'c ParseConfig,
+        tracer: Option<&'t mut dyn Tracer>,
+    ) -> Self {
+        Parser {
+            tokens,
+            split: Vec::new(),
+            table: &config.table,
+            implicit_mul: config.implicit_mul,
+            depth: 0,
+            max_depth: config.max_depth,
+            tracer,
+            src: None,
+            parens: 0,
+            last_end: 0,
+            after_number: false,
+        }
+    }
+
+    /// Next token, an operator symbol unknown to the table being split into
+    /// the longest known ones
+    fn peek(&mut self) -> Option<&Token<'a>> {
+        if self.split.is_empty() {
+            let parts = match self.tokens.peek() {
                 Some(Token {
-                    value: TokenKind::Minus,
+                    value: value @ TokenKind::Op(symbol),
                     loc,
-                }) => UniOp::minus(loc),
-                _ => unreachable!(),
+                }) if !self.table.contains(value) => Some(split_symbol(symbol, loc, self.table)),
+                _ => None,
             };
-            // , ATOM
-            let e = parse_atom(tokens)?;
-            let loc = op.loc.merge(&e.loc);
-            Ok(Ast::uniop(op, e, loc))
+            if let Some(parts) = parts {
+                self.tokens.next();
+                self.split = parts;
+            }
         }
-        // | ATOM
-        _ => parse_atom(tokens),
-    };
-    // eprintln!("EXPR1: {:?}", ret);
-    ret
-}
+        match self.split.last() {
+            Some(token) => Some(token),
+            None => self.tokens.peek(),
+        }
+    }
 
-/// Parse ATOM
-///
-/// ATOM = UNUMBER | "(", EXPR3, ")"
-fn parse_atom<T>(tokens: &mut Peekable<T>) -> Result<Ast>
-where
-    T: Iterator<Item = Token>,
-{
-    // eprintln!("ATOM --");
-    let ret = tokens
-        .next()
-        .ok_or(ParseError::Eof)
-        .and_then(|token| match token.value {
-            // UNUMBER
-            TokenKind::Number(n) => Ok(Ast::num(n, token.loc)),
-            // "(", EXPR3, ")"
-            TokenKind::LParen => {
-                let e = parse_expr3(tokens)?;
-                match tokens.next() {
-                    Some(Token {
-                        value: TokenKind::RParen,
+    fn bump(&mut self) -> Option<Token<'a>> {
+        self.peek();
+        let token = self.split.pop().or_else(|| self.tokens.next())?;
+        self.last_end = token.loc.1;
+        self.after_number = matches!(
+            token.value,
+            TokenKind::Number(_) | TokenKind::Decimal(_) | TokenKind::Imaginary(_)
+        );
+        Some(token)
+    }
+
+    /// Whether the next token starts a new statement of a program, i.e. it
+    /// is on another line than the last one and no parenthesis is open
+    fn at_line_break(&mut self) -> bool {
+        match self.src {
+            Some(src) if self.parens == 0 && self.split.is_empty() => {
+                let last_end = self.last_end;
+                self.tokens
+                    .peek()
+                    .is_some_and(|token| src[last_end..token.loc.0].contains('\n'))
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, expected: &TokenKind<'static>) -> Result<Token<'a>> {
+        match self.tokens.next() {
+            Some(token) if token.value == *expected => Ok(token),
+            found => Err(ParseError::expected(
+                vec![Expected::Token(expected.clone())],
+                found,
+            )),
+        }
+    }
+
+    fn expect_ident(&mut self) -> Result<String> {
+        match self.tokens.next() {
+            Some(Token {
+                value: TokenKind::Ident(name),
+                ..
+            }) => Ok(name.into_owned()),
+            found => Err(ParseError::expected(vec![Expected::Ident], found)),
+        }
+    }
+
+    /// Expect the end of the input after an expression, or in a program the
+    /// end of the statement
+    fn expect_end(&mut self) -> Result<()> {
+        if self.src.is_some() {
+            if self.at_line_break() {
+                return Ok(());
+            }
+            if self.split.is_empty() {
+                match self.tokens.peek() {
+                    None => return Ok(()),
+                    Some(token) if token.value == TokenKind::Semicolon => {
+                        self.tokens.next();
+                        return Ok(());
+                    }
+                    Some(_) => {}
+                }
+            }
+            return Err(ParseError::expected(
+                vec![
+                    Expected::Operator,
+                    Expected::Token(TokenKind::Semicolon),
+                    Expected::EndOfLine,
+                ],
+                self.bump(),
+            ));
+        }
+        match self.bump() {
+            Some(token) => Err(ParseError::expected(
+                vec![Expected::Operator, Expected::EndOfInput],
+                Some(token),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Tokens which can start an OPERAND
+    fn operand_expected(&self) -> Vec<Expected> {
+        let mut expected = vec![
+            Expected::Number,
+            Expected::Ident,
+            Expected::Token(TokenKind::LParen),
+        ];
+        // the functions such as `re` are covered by "identifier"
+        expected.extend(
+            self.table
+                .prefix_ops()
+                .filter(|op| !matches!(op.token, TokenKind::Ident(_)))
+                .map(|op| Expected::Token(op.token.clone())),
+        );
+        expected
+    }
+
+    fn parse(&mut self) -> Result<Ast> {
+        let ret = self.parse_expr()?;
+        self.expect_end()?;
+        Ok(ret)
+    }
+
+    /// Parse STMT
+    ///
+    /// STMT = DECL | EXPR
+    fn parse_stmt(&mut self) -> Result<Stmt> {
+        let fixity = match self.tokens.peek() {
+            Some(Token {
+                value: TokenKind::Ident(name),
+                ..
+            }) => Fixity::from_keyword(name),
+            _ => None,
+        };
+        let ret = match fixity {
+            Some(fixity) => Stmt::OpDecl(self.parse_decl(fixity)?),
+            None => Stmt::Expr(self.parse_expr()?),
+        };
+        self.expect_end()?;
+        Ok(ret)
+    }
+
+    /// Parse DECL
+    ///
+    /// DECL   = ("infixl" | "infixr" | "infix"), LEVEL, SYMBOL, "(", IDENT, ",", IDENT, ")", "=", EXPR
+    ///        | ("prefix" | "postfix"), SYMBOL, "(", IDENT, ")", "=", EXPR
+    /// LEVEL  = [0-9]
+    ///
+    /// The body is parsed with the operators declared so far, so an operator
+    /// cannot refer to itself.
+    fn parse_decl(&mut self, fixity: Fixity) -> Result<OpDecl> {
+        self.tokens.next();
+        let bp = match fixity {
+            Fixity::Infix(_) => match self.tokens.next() {
+                Some(Token {
+                    value: TokenKind::Number(n),
+                    ..
+                }) if n <= u64::from(MAX_LEVEL) => n as u8,
+                Some(
+                    token @ Token {
+                        value: TokenKind::Number(_),
                         ..
-                    }) => Ok(e),
-                    Some(token) => Err(ParseError::RedundantExpression(token)),
-                    _ => Err(ParseError::UnclosedOpenParen(token)),
+                    },
+                ) => return Err(ParseError::InvalidLevel(token.into_owned())),
+                found => return Err(ParseError::expected(vec![Expected::Number], found)),
+            },
+            Fixity::Prefix => PREFIX_BP,
+            Fixity::Postfix => POSTFIX_BP,
+        };
+        let symbol = match self.tokens.next() {
+            Some(
+                token @ Token {
+                    value:
+                        TokenKind::Op(_)
+                        | TokenKind::Plus
+                        | TokenKind::Minus
+                        | TokenKind::Asterisk
+                        | TokenKind::Slash,
+                    ..
+                },
+            ) => token.into_owned(),
+            found => return Err(ParseError::expected(vec![Expected::Operator], found)),
+        };
+
+        self.expect(&TokenKind::LParen)?;
+        let mut params = vec![self.expect_ident()?];
+        if let Fixity::Infix(_) = fixity {
+            self.expect(&TokenKind::Comma)?;
+            params.push(self.expect_ident()?);
+        }
+        self.expect(&TokenKind::RParen)?;
+        match self.tokens.next() {
+            Some(Token {
+                value: TokenKind::Op(ref s),
+                ..
+            }) if s == "=" => {}
+            // the lexer reads e.g. `=-x` as the symbol `=-`, whose rest starts
+            // the body
+            Some(Token {
+                value: TokenKind::Op(ref s),
+                loc,
+            }) if s.starts_with('=') => {
+                let rest = sub_symbol(s, 1, s.len());
+                self.split = split_symbol(&rest, &Loc(loc.0 + 1, loc.1), self.table);
+            }
+            found => {
+                return Err(ParseError::expected(
+                    vec![Expected::Token(TokenKind::symbol("="))],
+                    found,
+                ))
+            }
+        }
+        let body = self.parse_expr()?;
+
+        Ok(OpDecl {
+            fixity,
+            bp,
+            symbol,
+            params,
+            body,
+        })
+    }
+
+    /// Run `f` as `rule`, reporting it to the tracer if any
+    fn traced<U>(
+        &mut self,
+        rule: &'static str,
+        f: impl FnOnce(&mut Self) -> Result<Annot<U>>,
+    ) -> Result<Annot<U>> {
+        if self.tracer.is_some() {
+            let next = self.peek().cloned();
+            self.tracer.as_mut().unwrap().enter(rule, next.as_ref());
+        }
+        let ret = f(self);
+        if let Some(tracer) = self.tracer.as_mut() {
+            tracer.exit(rule, ret.as_ref().map(|annot| &annot.loc));
+        }
+        ret
+    }
+
+    /// Parse EXPR
+    ///
+    /// EXPR = OPERAND (INFIX OPERAND | POSTFIX)*
+    ///
+    /// where the operators come from the operator table.
+    fn parse_expr(&mut self) -> Result<Ast> {
+        self.traced("EXPR", |p| p.parse_bp(0))
+    }
+
+    /// Parse an expression made of operators binding at least as tight as
+    /// `min_bp`
+    fn parse_bp(&mut self, min_bp: u16) -> Result<Ast> {
+        if self.depth >= self.max_depth {
+            if let Some(token) = self.peek() {
+                return Err(ParseError::TooDeep {
+                    token: token.clone().into_owned(),
+                    limit: self.max_depth,
+                });
+            }
+        }
+        self.depth += 1;
+        let ret = self.parse_bp_nested(min_bp);
+        self.depth -= 1;
+        ret
+    }
+
+    fn parse_bp_nested(&mut self, min_bp: u16) -> Result<Ast> {
+        let mut lhs = self.parse_operand()?;
+        // binding power of the last non-associative operator in this loop
+        let mut nonassoc_bp = None;
+        let table = self.table;
+        let implicit_mul = self.implicit_mul;
+        let juxtaposition = InfixOp::juxtaposition();
+
+        while !self.at_line_break() {
+            let after_number = self.after_number;
+            let token = match self.peek() {
+                Some(token) => token,
+                None => break,
+            };
+            // the operator, and whether it is a token to consume
+            let infix = match table.infix(&token.value) {
+                Some(op) => Some((op, true)),
+                None if implicit_mul && starts_atom(&token.value) => Some((&juxtaposition, false)),
+                // a quantity such as `3 m`
+                None if after_number && is_unit(&token.value) => Some((&juxtaposition, false)),
+                None => None,
+            };
+            if let Some((op, explicit)) = infix {
+                let (l_bp, r_bp) = op.binding();
+                if l_bp < min_bp {
+                    break;
+                }
+                if nonassoc_bp == Some(op.bp) {
+                    return Err(ParseError::NonAssociative(token.clone().into_owned()));
                 }
+                nonassoc_bp = if op.assoc == Assoc::None {
+                    Some(op.bp)
+                } else {
+                    None
+                };
+                let op = if explicit {
+                    BinOp::new(op.kind.clone(), self.bump().unwrap().loc)
+                } else {
+                    BinOp::new(op.kind.clone(), Loc(token.loc.0, token.loc.0))
+                };
+                let rhs = self.parse_bp(r_bp)?;
+                let loc = lhs.loc.merge(&rhs.loc);
+                lhs = Ast::binop(op, lhs, rhs, loc);
+            } else if let Some(op) = table.postfix(&token.value) {
+                if op.left_bp() < min_bp {
+                    break;
+                }
+                let op = UniOp::new(op.kind.clone(), token.loc.clone());
+                self.bump();
+                let loc = lhs.loc.merge(&op.loc);
+                lhs = Ast::uniop(op, lhs, loc);
+            } else {
+                break;
             }
-            _ => Err(ParseError::NotExpression(token)),
-        });
-    // eprintln!("ATOM: {:?}", ret);
-    ret
+        }
+
+        Ok(lhs)
+    }
+
+    /// Parse OPERAND
+    ///
+    /// OPERAND = PREFIX OPERAND' | ATOM
+    ///
+    /// where OPERAND' is an OPERAND followed by the operators binding tighter
+    /// than the prefix, so that prefix operators nest as in `- -1`.
+    fn parse_operand(&mut self) -> Result<Ast> {
+        let table = self.table;
+        let prefix = match self.peek() {
+            Some(token) => table.prefix(&token.value),
+            None => None,
+        };
+        match prefix {
+            Some(op) => self.traced("PREFIX", |p| {
+                let r_bp = op.right_bp();
+                let op = UniOp::new(op.kind.clone(), p.bump().unwrap().loc);
+                let e = p.parse_bp(r_bp)?;
+                let loc = op.loc.merge(&e.loc);
+                Ok(Ast::uniop(op, e, loc))
+            }),
+            None => self.parse_atom(),
+        }
+    }
+
+    /// Parse ATOM
+    ///
+    /// ATOM = UNUMBER | DECIMAL | IMAGINARY | IDENT | "(", EXPR, ")"
+    fn parse_atom(&mut self) -> Result<Ast> {
+        self.traced("ATOM", |p| {
+            let token = match p.bump() {
+                Some(token) => token,
+                None => return Err(ParseError::expected(p.operand_expected(), None)),
+            };
+            match token.value {
+                // UNUMBER
+                TokenKind::Number(n) => Ok(Ast::num(n, token.loc)),
+                // DECIMAL
+                TokenKind::Decimal(d) => Ok(Ast::new(AstNode::Decimal(d), token.loc)),
+                // IMAGINARY
+                TokenKind::Imaginary(d) => Ok(Ast::new(AstNode::Imaginary(d), token.loc)),
+                // IDENT
+                TokenKind::Ident(ref name) => Ok(Ast::var(name, token.loc)),
+                // "(", EXPR, ")"
+                TokenKind::LParen => {
+                    p.parens += 1;
+                    let e = p.parse_expr();
+                    p.parens -= 1;
+                    let e = e.map_err(|err| match err {
+                        // the input ended inside the parentheses
+                        ParseError::ExpectedToken {
+                            expected,
+                            found: None,
+                            opener: None,
+                        } => ParseError::ExpectedToken {
+                            expected,
+                            found: None,
+                            opener: Some(token.clone().into_owned()),
+                        },
+                        err => err,
+                    })?;
+                    match p.bump() {
+                        Some(Token {
+                            value: TokenKind::RParen,
+                            ..
+                        }) => Ok(e),
+                        found => Err(ParseError::ExpectedToken {
+                            expected: vec![Expected::Token(TokenKind::RParen), Expected::Operator],
+                            found: found.map(Token::into_owned),
+                            opener: Some(token.into_owned()),
+                        }),
+                    }
+                }
+                _ => Err(ParseError::expected(p.operand_expected(), Some(token))),
+            }
+        })
+    }
+}
+
+/// Whether `token` is the name of a unit, e.g. `km`
+fn is_unit(token: &TokenKind<'_>) -> bool {
+    matches!(token, TokenKind::Ident(name) if units::lookup(name).is_some())
+}
+
+/// Whether `token` is the first token of an ATOM
+fn starts_atom(token: &TokenKind<'_>) -> bool {
+    matches!(
+        token,
+        TokenKind::Number(_)
+            | TokenKind::Decimal(_)
+            | TokenKind::Imaginary(_)
+            | TokenKind::Ident(_)
+            | TokenKind::LParen
+    )
+}
+
+/// Split the operator symbol `s` at `loc` into the longest operators known
+/// to `table`, returning the parts in reverse order
+///
+/// A rest which does not start with any known operator is kept whole. Only
+/// prefixes up to the longest known symbol are tried, so that long runs of
+/// symbols take linear time.
+fn split_symbol<'a>(s: &Cow<'a, str>, loc: &Loc, table: &OperatorTable) -> Vec<Token<'a>> {
+    let symbols: Vec<&str> = table.symbols().collect();
+    let longest = symbols.iter().map(|symbol| symbol.len()).max().unwrap_or(0);
+    let mut parts = Vec::new();
+    let mut start = 0;
+    while start < s.len() {
+        // symbol characters are ASCII, so every offset is a char boundary
+        let end = (start + 1..=s.len().min(start + longest))
+            .rev()
+            .find(|&end| symbols.contains(&&s[start..end]))
+            .unwrap_or(s.len());
+        let symbol = match TokenKind::symbol(&s[start..end]) {
+            TokenKind::Op(_) => TokenKind::Op(sub_symbol(s, start, end)),
+            builtin => builtin.into_owned(),
+        };
+        parts.push(Token::new(symbol, Loc(loc.0 + start, loc.0 + end)));
+        start = end;
+    }
+    parts.reverse();
+    parts
+}
+
+/// Part of the symbol `s` from `start` to `end`, borrowing from the input if
+/// `s` does
+fn sub_symbol<'a>(s: &Cow<'a, str>, start: usize, end: usize) -> Cow<'a, str> {
+    match s {
+        Cow::Borrowed(s) => Cow::Borrowed(&s[start..end]),
+        Cow::Owned(s) => Cow::Owned(s[start..end].to_string()),
+    }
 }
 
 #[cfg(test)]
@@ -423,4 +1551,291 @@ mod tests {
             ))
         )
     }
+
+    #[test]
+    fn test_parser_errors() {
+        let err = |s: &str| match s.parse::<Ast>() {
+            Err(Error::Parser(err)) => err,
+            ret => panic!("{:?}", ret),
+        };
+        let paren = vec![Expected::Token(TokenKind::RParen), Expected::Operator];
+        assert_eq!(
+            err("(1 2"),
+            ParseError::ExpectedToken {
+                expected: paren.clone(),
+                found: Some(Token::number(2, Loc(3, 4))),
+                opener: Some(Token::lparen(Loc(0, 1))),
+            }
+        );
+        assert_eq!(
+            err("((1) + 2"),
+            ParseError::ExpectedToken {
+                expected: paren,
+                found: None,
+                opener: Some(Token::lparen(Loc(0, 1))),
+            }
+        );
+        assert_eq!(
+            err("1 * )").to_string(),
+            "4-5: expected number, identifier, `(`, `+` or `-`, found `)`"
+        );
+        assert_eq!(
+            err("(1 +").to_string(),
+            "expected number, identifier, `(`, `+` or `-`, found end of input"
+        );
+        assert_eq!(
+            err("(1 2").to_string(),
+            "3-4: expected `)` or operator, found `2`"
+        );
+    }
+
+    #[test]
+    fn test_parser_depth() {
+        let deep = |depth| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        let parse = |s: &str, max_depth| {
+            let config = ParseConfig::new().max_depth(max_depth);
+            parse_tokens_with(Lexer::new(s), &config, None)
+        };
+        assert!(parse(&deep(DEFAULT_MAX_DEPTH - 1), DEFAULT_MAX_DEPTH).is_ok());
+        match parse("((1)) + -(2)", 3) {
+            Err(Error::Parser(err)) => assert_eq!(
+                err.to_string(),
+                "10-11: expression is nested deeper than 3 levels"
+            ),
+            ret => panic!("{:?}", ret),
+        }
+        match parse(&deep(100_000), DEFAULT_MAX_DEPTH) {
+            Err(Error::Parser(ParseError::TooDeep { token, limit })) => {
+                assert_eq!(token, Token::lparen(Loc(256, 257)));
+                assert_eq!(limit, DEFAULT_MAX_DEPTH);
+            }
+            ret => panic!("{:?}", ret),
+        }
+        // a long run of symbols is split in linear time
+        let minuses = format!("1 {}1", "-".repeat(100_000));
+        match parse(&minuses, DEFAULT_MAX_DEPTH) {
+            Err(Error::Parser(ParseError::TooDeep { token, .. })) => {
+                assert_eq!(token.value, TokenKind::Minus);
+            }
+            ret => panic!("{:?}", ret),
+        }
+    }
+
+    #[test]
+    fn test_parser_table() {
+        use crate::lexer::{Lexer, TokenKind};
+        use crate::operator::{Assoc, OperatorTable};
+
+        let mut table = OperatorTable::default();
+        table.add_infix(TokenKind::Minus, BinOpKind::Sub, 6, Assoc::Right);
+        table.add_infix(TokenKind::Slash, BinOpKind::Div, 7, Assoc::None);
+        let config = ParseConfig::new().table(table);
+        let parse = |s| parse_tokens_with(Lexer::new(s), &config, None);
+
+        assert_eq!(
+            parse("1 - 2 - 3").unwrap(),
+            Ast::binop(
+                BinOp::sub(Loc(2, 3)),
+                Ast::num(1, Loc(0, 1)),
+                Ast::binop(
+                    BinOp::sub(Loc(6, 7)),
+                    Ast::num(2, Loc(4, 5)),
+                    Ast::num(3, Loc(8, 9)),
+                    Loc(4, 9)
+                ),
+                Loc(0, 9)
+            )
+        );
+        assert_eq!(
+            parse("1 / 2 * 3"),
+            Err(Error::Parser(ParseError::NonAssociative(Token::asterisk(
+                Loc(6, 7)
+            ))))
+        );
+        // the default table keeps the standard grammar
+        assert_eq!(
+            parse_tokens_with(Lexer::new("1 - 2 - 3"), &ParseConfig::new(), None),
+            "1 - 2 - 3".parse::<Ast>()
+        );
+    }
+
+    #[test]
+    fn test_parser_implicit_mul() {
+        use crate::lexer::Lexer;
+        use crate::printer::PrettyPrinter;
+
+        let config = ParseConfig::new().implicit_mul(true);
+        let parse = |s| parse_tokens_with(Lexer::new(s), &config, None);
+        assert_eq!(
+            parse("2(3)"),
+            Ok(Ast::binop(
+                BinOp::mul(Loc(1, 1)),
+                Ast::num(2, Loc(0, 1)),
+                Ast::num(3, Loc(2, 3)),
+                Loc(0, 3)
+            ))
+        );
+        let printer = PrettyPrinter::new();
+        for (src, expected) in &[
+            ("(1+2)(3+4)", "(1 + 2) * (3 + 4)"),
+            ("1/2x y", "1 / (2 * x * y)"),
+            ("-2x*3", "-2 * x * 3"),
+        ] {
+            assert_eq!(printer.print_flat(&parse(src).unwrap()), *expected);
+        }
+        // a number and a unit are always a quantity
+        let parse = |s| parse_tokens_with(Lexer::new(s), &ParseConfig::new(), None);
+        assert_eq!(
+            printer.print_flat(&parse("-2.5 km/h in m/s").unwrap()),
+            "-2.5 km / h in m / s"
+        );
+        for (src, loc) in &[("2 abs -3", Loc(2, 5)), ("2 x", Loc(2, 3))] {
+            assert_eq!(
+                parse(src),
+                Err(Error::Parser(ParseError::expected(
+                    vec![Expected::Operator, Expected::EndOfInput],
+                    Some(Token::ident(&src[2..loc.1], loc.clone()))
+                )))
+            );
+        }
+        assert_eq!(
+            parse("2(3)"),
+            Err(Error::Parser(ParseError::expected(
+                vec![Expected::Operator, Expected::EndOfInput],
+                Some(Token::lparen(Loc(1, 2)))
+            )))
+        );
+    }
+
+    #[test]
+    fn test_parser_decl() {
+        use crate::lexer::Lexer;
+        use crate::printer::PrettyPrinter;
+
+        let mut config = ParseConfig::new();
+        let stmt = |config: &ParseConfig, s| parse_stmt_with(Lexer::new(s), config, None);
+        let decl = match stmt(&config, "infixr 8 <^> (a, b) = a*-b") {
+            Ok(Stmt::OpDecl(decl)) => decl,
+            ret => panic!("{:?}", ret),
+        };
+        assert_eq!(decl.fixity, Fixity::Infix(Assoc::Right));
+        assert_eq!(decl.params, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(PrettyPrinter::new().print_flat(&decl.body), "a * -b");
+        match stmt(&config, "prefix ~ (x) =-x") {
+            Ok(Stmt::OpDecl(decl)) => assert_eq!(
+                decl.body,
+                Ast::uniop(
+                    UniOp::minus(Loc(14, 15)),
+                    Ast::var("x", Loc(15, 16)),
+                    Loc(14, 16)
+                )
+            ),
+            ret => panic!("{:?}", ret),
+        }
+        assert_eq!(
+            stmt(&config, "1 <^> 2"),
+            Err(Error::Parser(ParseError::expected(
+                vec![Expected::Operator, Expected::EndOfInput],
+                Some(Token::op("<^>", Loc(2, 5)))
+            )))
+        );
+        config.declare(&decl).unwrap();
+        assert_eq!(
+            stmt(&config, "1 <^> 2"),
+            Ok(Stmt::Expr(Ast::binop(
+                BinOp::new(BinOpKind::Custom("<^>".to_string()), Loc(2, 5)),
+                Ast::num(1, Loc(0, 1)),
+                Ast::num(2, Loc(6, 7)),
+                Loc(0, 7)
+            )))
+        );
+
+        let conflict = |s| match stmt(&config, s) {
+            Ok(Stmt::OpDecl(decl)) => config.clone().declare(&decl),
+            ret => panic!("{:?}", ret),
+        };
+        assert_eq!(
+            conflict("prefix - (x) = x"),
+            Err(ParseError::BuiltinOperator(Token::minus(Loc(7, 8))))
+        );
+        assert_eq!(
+            conflict("postfix <^> (x) = x"),
+            Err(ParseError::OperatorConflict(Token::op("<^>", Loc(8, 11))))
+        );
+        assert_eq!(
+            stmt(&config, "infix 10 <> (a, b) = a"),
+            Err(Error::Parser(ParseError::InvalidLevel(Token::number(
+                10,
+                Loc(6, 8)
+            ))))
+        );
+    }
+
+    #[test]
+    fn test_parse_program() {
+        use crate::printer::PrettyPrinter;
+
+        let src = "1 + 2; 3\n\n4 *\n5 // five\n(6\n+ 7);;\ninfixl 6 <+> (a, b) =\na - b\n1 <+> 2\n";
+        let Program { stmts, locs } = src.parse::<Program>().unwrap();
+        let spans: Vec<_> = locs.iter().map(|loc| &src[loc.0..loc.1]).collect();
+        assert_eq!(
+            spans,
+            [
+                "1 + 2",
+                "3",
+                "4 *\n5",
+                "(6\n+ 7)",
+                "infixl 6 <+> (a, b) =\na - b",
+                "1 <+> 2"
+            ]
+        );
+        let mut config = ParseConfig::new();
+        for stmt in &stmts {
+            if let Stmt::OpDecl(decl) = stmt {
+                config.declare(decl).unwrap();
+            }
+        }
+        let printer = PrettyPrinter::new().table(config.operators().clone());
+        let printed: Vec<_> = stmts
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::Expr(ast) => printer.print_flat(ast),
+                Stmt::OpDecl(decl) => printer.print_flat(&decl.body),
+            })
+            .collect();
+        assert_eq!(
+            printed,
+            ["1 + 2", "3", "4 * 5", "6 + 7", "a - b", "1 <+> 2"]
+        );
+
+        assert_eq!(
+            "prefix ~ (x) = -x\n~1 ~ 2".parse::<Program>(),
+            Err(Error::Parser(ParseError::expected(
+                vec![
+                    Expected::Operator,
+                    Expected::Token(TokenKind::Semicolon),
+                    Expected::EndOfLine
+                ],
+                Some(Token::op("~", Loc(21, 22)))
+            )))
+        );
+        assert_eq!(line_col("1;\n2 + é", 9), (2, 6));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let ast = "1 + 2 * -3".parse::<Ast>().unwrap();
+        let json = serde_json::to_string(&ast).unwrap();
+        assert_eq!(serde_json::from_str::<Ast>(&json).unwrap(), ast);
+        let ast = format!("{}1", "1+".repeat(100_000)).parse::<Ast>().unwrap();
+        let json = serde_json::to_string(&ast).unwrap();
+        assert_eq!(serde_json::from_str::<Ast>(&json).unwrap(), ast);
+        let json = r#"{"value": [{"Num": 1}, {"UniOp": {"op": {"value": "Minus", "loc": [0, 1]}, "e": [1, 2]}}, {"Num": 2}], "loc": [0, 2]}"#;
+        assert!(serde_json::from_str::<Ast>(json).is_err());
+
+        let err = "(1".parse::<Ast>().unwrap_err();
+        let json = serde_json::to_string(&err).unwrap();
+        assert_eq!(serde_json::from_str::<Error>(&json).unwrap(), err);
+    }
 }
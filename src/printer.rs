@@ -0,0 +1,626 @@
+use std::fmt::Write;
+use std::rc::Rc;
+
+use super::lexer::{is_symbol_char, Lexer, TokenKind};
+use super::operator::{Assoc, InfixOp, OperatorTable, PostfixOp, PrefixOp};
+use super::parser::{Ast, AstNode, BinOpKind, Fixity, OpDecl};
+use super::units;
+
+/// Prints an `Ast` back to source in canonical style.
+///
+/// Operators are surrounded by single spaces, parentheses are emitted only
+/// where the operator table needs them, and expressions wider than `width`
+/// are broken after their lowest-precedence operators. A number juxtaposed
+/// with a unit, as in `3 m`, stays so.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrettyPrinter {
+    width: usize,
+    indent: usize,
+    table: OperatorTable,
+    /// Source the printed trees were parsed from, giving the spelling of
+    /// their literals
+    source: Option<Rc<str>>,
+}
+
+impl Default for PrettyPrinter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Operator of a node as found in the operator table
+#[derive(Debug, Clone)]
+enum NodeOp {
+    Atom,
+    Prefix(PrefixOp),
+    Infix(InfixOp),
+    Postfix(PostfixOp),
+}
+
+/// Where a node appears relative to its parent
+#[derive(Debug, Clone, Copy)]
+enum Position<'a> {
+    InfixLeft(&'a InfixOp),
+    InfixRight(&'a InfixOp),
+    PrefixOperand(&'a PrefixOp),
+    PostfixOperand(&'a PostfixOp),
+}
+
+/// Pending work of the printing loops, kept on an explicit stack so that
+/// trees of any depth can be printed
+#[derive(Debug)]
+enum Task<'a> {
+    /// Write the node on a single line
+    Flat(&'a Ast),
+    /// Write the node broken into lines, indenting them by the given amount
+    Expr(&'a Ast, usize),
+    /// Like `Expr`, for an operand which needs parentheses if the flag is set
+    Operand(&'a Ast, bool, usize),
+    Text(String),
+    /// Separate the operand of a prefix operator starting at this offset
+    Separate(usize),
+    Newline(usize),
+}
+
+impl PrettyPrinter {
+    pub fn new() -> Self {
+        PrettyPrinter {
+            width: 80,
+            indent: 4,
+            table: OperatorTable::default(),
+            source: None,
+        }
+    }
+
+    /// Set the maximum line width.
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = width;
+        self
+    }
+
+    /// Set the operators the output will be parsed with.
+    pub fn table(mut self, table: OperatorTable) -> Self {
+        self.table = table;
+        self
+    }
+
+    /// Print the literals as they are spelled in `src`, the source of the
+    /// trees to print, e.g. `0xFF` instead of `255`.
+    pub fn source(mut self, src: &str) -> Self {
+        self.source = Some(src.into());
+        self
+    }
+
+    pub fn print(&self, expr: &Ast) -> String {
+        let mut buf = String::new();
+        self.write_expr(expr, 0, &mut buf);
+        buf
+    }
+
+    /// Print `decl`, its body being broken like `print` would.
+    pub fn print_decl(&self, decl: &OpDecl) -> String {
+        let mut buf = String::from(decl.fixity.keyword());
+        if let Fixity::Infix(_) = decl.fixity {
+            write!(buf, " {}", decl.bp).unwrap();
+        }
+        write!(
+            buf,
+            " {} ({}) = ",
+            decl.symbol.value,
+            decl.params.join(", ")
+        )
+        .unwrap();
+        self.write_expr(&decl.body, 0, &mut buf);
+        buf
+    }
+
+    /// Print `expr` on a single line regardless of the width.
+    pub fn print_flat(&self, expr: &Ast) -> String {
+        let mut buf = String::new();
+        self.write_flat(expr, usize::MAX, &mut buf);
+        buf
+    }
+
+    /// Print `expr` on a single line if it is at most `max` bytes long
+    fn print_within(&self, expr: &Ast, max: usize) -> Option<String> {
+        let mut buf = String::new();
+        if self.write_flat(expr, max, &mut buf) {
+            Some(buf)
+        } else {
+            None
+        }
+    }
+
+    /// Write `expr` on a single line, giving up as soon as it is known to
+    /// make `buf` longer than `max`; returns whether it was written entirely
+    fn write_flat(&self, expr: &Ast, max: usize, buf: &mut String) -> bool {
+        // every pending task but `Separate` writes at least a byte
+        let len = |task: &Task| match task {
+            Task::Text(text) => text.len(),
+            Task::Separate(_) => 0,
+            _ => 1,
+        };
+        let mut tasks = vec![Task::Flat(expr)];
+        let mut pending = 1;
+        while let Some(task) = tasks.pop() {
+            if buf.len() + pending > max {
+                return false;
+            }
+            pending -= len(&task);
+            let pushed = tasks.len();
+            let expr = match task {
+                Task::Flat(expr) => expr,
+                Task::Text(text) => {
+                    buf.push_str(&text);
+                    continue;
+                }
+                Task::Separate(start) => {
+                    separate_symbols(buf, start);
+                    continue;
+                }
+                _ => unreachable!(),
+            };
+            if let Some(spelling) = self.spelling(expr) {
+                buf.push_str(spelling);
+                continue;
+            }
+            match (&expr.value, self.node_op(expr)) {
+                (AstNode::UniOp { e, .. }, NodeOp::Prefix(op)) => {
+                    buf.push_str(&op.token.to_string());
+                    tasks.push(Task::Separate(buf.len()));
+                    self.push_flat_operand(e, Position::PrefixOperand(&op), &mut tasks);
+                }
+                (AstNode::UniOp { e, .. }, NodeOp::Postfix(op)) => {
+                    tasks.push(Task::Text(op.token.to_string()));
+                    self.push_flat_operand(e, Position::PostfixOperand(&op), &mut tasks);
+                }
+                (AstNode::BinOp { l, r, .. }, NodeOp::Infix(op)) => {
+                    self.push_flat_operand(r, Position::InfixRight(&op), &mut tasks);
+                    if self.is_quantity(expr) {
+                        tasks.push(Task::Text(" ".to_string()));
+                    } else {
+                        tasks.push(Task::Text(format!(" {} ", op.token)));
+                    }
+                    self.push_flat_operand(l, Position::InfixLeft(&op), &mut tasks);
+                }
+                (AstNode::Num(n), _) => buf.push_str(&n.to_string()),
+                (AstNode::Decimal(d), _) => buf.push_str(&d.to_string()),
+                (AstNode::Imaginary(d), _) => buf.push_str(&format!("{}i", d)),
+                (AstNode::Var(name), _) => buf.push_str(name),
+                _ => unreachable!(),
+            }
+            pending += tasks[pushed..].iter().map(len).sum::<usize>();
+        }
+        buf.len() <= max
+    }
+
+    /// Schedule `e` to be written flat, parenthesized if needed
+    fn push_flat_operand<'a>(&self, e: &'a Ast, pos: Position, tasks: &mut Vec<Task<'a>>) {
+        if self.needs_paren(e, pos) {
+            tasks.push(Task::Text(")".to_string()));
+            tasks.push(Task::Flat(e));
+            tasks.push(Task::Text("(".to_string()));
+        } else {
+            tasks.push(Task::Flat(e));
+        }
+    }
+
+    /// Write `expr` broken into lines to fit the width, continuation lines
+    /// being indented by `indent`
+    fn write_expr(&self, expr: &Ast, indent: usize, buf: &mut String) {
+        let mut tasks = vec![Task::Expr(expr, indent)];
+        while let Some(task) = tasks.pop() {
+            match task {
+                Task::Expr(expr, indent) => self.expr_task(expr, indent, buf, &mut tasks),
+                Task::Operand(e, paren, indent) => {
+                    self.operand_task(e, paren, indent, buf, &mut tasks)
+                }
+                Task::Text(text) => buf.push_str(&text),
+                Task::Separate(start) => separate_symbols(buf, start),
+                Task::Newline(indent) => newline(indent, buf),
+                Task::Flat(_) => unreachable!(),
+            }
+        }
+    }
+
+    fn expr_task<'a>(
+        &self,
+        expr: &'a Ast,
+        indent: usize,
+        buf: &mut String,
+        tasks: &mut Vec<Task<'a>>,
+    ) {
+        if let Some(flat) = self.print_within(expr, self.width.saturating_sub(column(buf))) {
+            buf.push_str(&flat);
+            return;
+        }
+
+        match (&expr.value, self.node_op(expr)) {
+            (AstNode::UniOp { e, .. }, NodeOp::Prefix(op)) => {
+                buf.push_str(&op.token.to_string());
+                tasks.push(Task::Separate(buf.len()));
+                let paren = self.needs_paren(e, Position::PrefixOperand(&op));
+                tasks.push(Task::Operand(e, paren, indent));
+            }
+            (AstNode::UniOp { e, .. }, NodeOp::Postfix(op)) => {
+                tasks.push(Task::Text(op.token.to_string()));
+                let paren = self.needs_paren(e, Position::PostfixOperand(&op));
+                tasks.push(Task::Operand(e, paren, indent));
+            }
+            // a quantity cannot be broken, a line break would end the statement
+            (AstNode::BinOp { .. }, NodeOp::Infix(root)) if !self.is_quantity(expr) => {
+                // flatten the chain of operators at this level along the left edge
+                let mut rest = Vec::new();
+                let mut first = expr;
+                while let (AstNode::BinOp { l, r, .. }, NodeOp::Infix(op)) =
+                    (&first.value, self.node_op(first))
+                {
+                    if op.bp != root.bp || self.needs_paren(l, Position::InfixLeft(&op)) {
+                        break;
+                    }
+                    rest.push((op, &**r));
+                    first = l;
+                }
+
+                // `rest` is in reverse order, as the tasks are pushed
+                let inner = indent + self.indent;
+                // the operators end the lines, so that a program does not
+                // read the next line as a new statement
+                for (op, r) in &rest {
+                    let paren = self.needs_paren(r, Position::InfixRight(op));
+                    tasks.push(Task::Operand(r, paren, inner));
+                    tasks.push(Task::Newline(inner));
+                    tasks.push(Task::Text(format!(" {}", op.token)));
+                }
+                match rest.last() {
+                    Some((op, _)) => {
+                        let paren = self.needs_paren(first, Position::InfixLeft(op));
+                        tasks.push(Task::Operand(first, paren, indent));
+                    }
+                    None => tasks.push(Task::Expr(first, indent)),
+                }
+            }
+            _ => buf.push_str(&self.print_flat(expr)),
+        }
+    }
+
+    fn operand_task<'a>(
+        &self,
+        e: &'a Ast,
+        paren: bool,
+        indent: usize,
+        buf: &mut String,
+        tasks: &mut Vec<Task<'a>>,
+    ) {
+        if !paren {
+            tasks.push(Task::Expr(e, indent));
+            return;
+        }
+
+        let max = self.width.saturating_sub(column(buf) + 2);
+        if let Some(flat) = self.print_within(e, max) {
+            buf.push('(');
+            buf.push_str(&flat);
+            buf.push(')');
+        } else {
+            let inner = indent + self.indent;
+            buf.push('(');
+            newline(inner, buf);
+            tasks.push(Task::Text(")".to_string()));
+            tasks.push(Task::Newline(indent));
+            tasks.push(Task::Expr(e, inner));
+        }
+    }
+
+    /// Spelling of the literal `e` in the source, if it was parsed from there
+    fn spelling(&self, e: &Ast) -> Option<&str> {
+        if let AstNode::Var(_) | AstNode::UniOp { .. } | AstNode::BinOp { .. } = e.value {
+            return None;
+        }
+        let lexeme = self.source.as_deref()?.get(e.loc.0..e.loc.1)?;
+        let same = match (Lexer::new(lexeme).lex().ok()?.as_slice(), &e.value) {
+            ([token], AstNode::Num(n)) => token.value == TokenKind::Number(*n),
+            ([token], AstNode::Decimal(d)) => token.value == TokenKind::Decimal(*d),
+            ([token], AstNode::Imaginary(d)) => token.value == TokenKind::Imaginary(*d),
+            _ => false,
+        };
+        if same {
+            Some(lexeme)
+        } else {
+            None
+        }
+    }
+
+    /// Whether `e` is a number juxtaposed with a unit, e.g. `3 m`, which
+    /// parses back the same without a `*`
+    fn is_quantity(&self, e: &Ast) -> bool {
+        let (op, l, r) = match &e.value {
+            AstNode::BinOp { op, l, r } => (op, l, r),
+            _ => return false,
+        };
+        let unit = matches!(&r.value, AstNode::Var(name) if units::lookup(name).is_some());
+        // the parser gives an empty location to the operator it inserts
+        if op.value != BinOpKind::Mul || op.loc.0 != op.loc.1 || !unit {
+            return false;
+        }
+        if self.needs_paren(l, Position::InfixLeft(&InfixOp::juxtaposition())) {
+            return false;
+        }
+        // the left operand has to be printed ending with the number
+        let mut last = &**l;
+        loop {
+            let (child, paren) = match (&last.value, self.node_op(last)) {
+                (AstNode::Num(_) | AstNode::Decimal(_) | AstNode::Imaginary(_), _) => return true,
+                (AstNode::BinOp { r, .. }, NodeOp::Infix(op)) => {
+                    (r, self.needs_paren(r, Position::InfixRight(&op)))
+                }
+                (AstNode::UniOp { e, .. }, NodeOp::Prefix(op)) => {
+                    (e, self.needs_paren(e, Position::PrefixOperand(&op)))
+                }
+                _ => return false,
+            };
+            if paren {
+                return false;
+            }
+            last = child;
+        }
+    }
+
+    /// Look up the operator of `e`, falling back to the default table for
+    /// operators missing from `self.table`; a quantity has the operator of
+    /// juxtaposition
+    fn node_op(&self, e: &Ast) -> NodeOp {
+        if self.is_quantity(e) {
+            return NodeOp::Infix(InfixOp::juxtaposition());
+        }
+        let lookup = |table: &OperatorTable| match e.value {
+            AstNode::Num(_) | AstNode::Decimal(_) | AstNode::Imaginary(_) | AstNode::Var(_) => {
+                Some(NodeOp::Atom)
+            }
+            AstNode::UniOp { ref op, .. } => table
+                .prefix_by_kind(&op.value)
+                .cloned()
+                .map(NodeOp::Prefix)
+                .or_else(|| {
+                    table
+                        .postfix_by_kind(&op.value)
+                        .cloned()
+                        .map(NodeOp::Postfix)
+                }),
+            AstNode::BinOp { ref op, .. } => {
+                table.infix_by_kind(&op.value).cloned().map(NodeOp::Infix)
+            }
+        };
+        lookup(&self.table)
+            .or_else(|| lookup(&OperatorTable::default()))
+            .expect("operator missing from the operator table")
+    }
+
+    /// Lowest binding power along the left edge of `e` printed without
+    /// parentheses
+    fn left_bp(&self, mut e: &Ast) -> u16 {
+        let mut bp = u16::MAX;
+        loop {
+            let (child, paren) = match (&e.value, self.node_op(e)) {
+                (AstNode::BinOp { l, .. }, NodeOp::Infix(op)) => {
+                    bp = bp.min(op.binding().0);
+                    (l, self.needs_paren(l, Position::InfixLeft(&op)))
+                }
+                (AstNode::UniOp { e, .. }, NodeOp::Postfix(op)) => {
+                    bp = bp.min(op.left_bp());
+                    (e, self.needs_paren(e, Position::PostfixOperand(&op)))
+                }
+                _ => return bp,
+            };
+            if paren {
+                return bp;
+            }
+            e = child;
+        }
+    }
+
+    /// Lowest binding power along the right edge of `e` printed without
+    /// parentheses
+    fn right_bp(&self, mut e: &Ast) -> u16 {
+        let mut bp = u16::MAX;
+        loop {
+            let (child, paren) = match (&e.value, self.node_op(e)) {
+                (AstNode::BinOp { r, .. }, NodeOp::Infix(op)) => {
+                    bp = bp.min(op.binding().1);
+                    (r, self.needs_paren(r, Position::InfixRight(&op)))
+                }
+                (AstNode::UniOp { e, .. }, NodeOp::Prefix(op)) => {
+                    bp = bp.min(op.right_bp());
+                    (e, self.needs_paren(e, Position::PrefixOperand(&op)))
+                }
+                _ => return bp,
+            };
+            if paren {
+                return bp;
+            }
+            e = child;
+        }
+    }
+
+    /// Whether `e` has to be parenthesized to be parsed back at `pos`
+    fn needs_paren(&self, e: &Ast, pos: Position) -> bool {
+        let op = self.node_op(e);
+        match pos {
+            Position::InfixLeft(parent) => {
+                parent.binding().0 >= self.right_bp(e) || nonassoc_conflict(parent, &op)
+            }
+            Position::InfixRight(parent) => {
+                self.left_bp(e) < parent.binding().1 || nonassoc_conflict(parent, &op)
+            }
+            Position::PrefixOperand(parent) => self.left_bp(e) < parent.right_bp(),
+            Position::PostfixOperand(parent) => parent.left_bp() >= self.right_bp(e),
+        }
+    }
+}
+
+/// Operators of the same binding power as a non-associative one cannot be
+/// chained with it
+fn nonassoc_conflict(parent: &InfixOp, op: &NodeOp) -> bool {
+    match op {
+        NodeOp::Infix(op) => {
+            op.bp == parent.bp && (op.assoc == Assoc::None || parent.assoc == Assoc::None)
+        }
+        _ => false,
+    }
+}
+
+/// Insert a space at `pos` if it is between two operator symbols, which would
+/// otherwise be read as a single one, e.g. in `- -1`, or after a function name
+/// unless a parenthesis follows, e.g. in `abs x` or `abs -3`
+fn separate_symbols(buf: &mut String, pos: usize) {
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$';
+    let (before, after) = (buf[..pos].chars().next_back(), buf[pos..].chars().next());
+    let symbols = before.is_some_and(is_symbol_char) && after.is_some_and(is_symbol_char);
+    let word = before.is_some_and(is_word) && after.is_some_and(|c| c != '(');
+    if symbols || word {
+        buf.insert(pos, ' ');
+    }
+}
+
+fn column(buf: &str) -> usize {
+    buf.len() - buf.rfind('\n').map_or(0, |i| i + 1)
+}
+
+fn newline(indent: usize, buf: &mut String) {
+    buf.push('\n');
+    buf.push_str(&" ".repeat(indent));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::lexer::TokenKind;
+    use crate::operator::POSTFIX_BP;
+    use crate::parser::{parse_tokens_with, BinOpKind, ParseConfig, Program, Stmt, UniOpKind};
+
+    fn pretty(s: &str, width: usize) -> String {
+        let ast = s.parse::<Ast>().unwrap();
+        PrettyPrinter::new().width(width).print(&ast)
+    }
+
+    #[test]
+    fn test_printer() {
+        assert_eq!(pretty("1+2*3 - -10", 80), "1 + 2 * 3 - -10");
+        assert_eq!(pretty("((1+2))+(3*4)", 80), "1 + 2 + 3 * 4");
+        assert_eq!(pretty("1-(2-3)", 80), "1 - (2 - 3)");
+        assert_eq!(pretty("(1-2)*-(3)", 80), "(1 - 2) * -3");
+        assert_eq!(pretty("-(1*2)", 80), "-(1 * 2)");
+        assert_eq!(pretty("--(2)", 80), "- -2");
+        assert_eq!(pretty("-+-1*2", 80), "- + -1 * 2");
+        assert_eq!(pretty("conj(re(z))*abs 2.50j", 80), "conj re z * abs 2.50i");
+        assert_eq!(pretty("abs -3 - abs(-(2))", 80), "abs -3 - abs -2");
+        assert_eq!(pretty("re(1 + 2i)", 80), "re(1 + 2i)");
+
+        let src = "0x_FF + (1_000 * 02.50j)";
+        let ast = src.parse::<Ast>().unwrap();
+        assert_eq!(PrettyPrinter::new().print(&ast), "255 + 1000 * 2.50i");
+        let printer = PrettyPrinter::new().source(src);
+        assert_eq!(printer.print(&ast), "0x_FF + 1_000 * 02.50j");
+        // trees not parsed from the source print their own literals
+        assert_eq!(printer.print(&"1.5 + 2".parse().unwrap()), "1.5 + 2");
+    }
+
+    #[test]
+    fn test_printer_wrap() {
+        assert_eq!(
+            pretty("111 + 222 * 333 - 444", 16),
+            "111 +\n    222 * 333 -\n    444"
+        );
+        assert_eq!(
+            pretty("1 * (222 + 333 + 444)", 16),
+            "1 *\n    (\n        222 +\n            333 +\n            444\n    )"
+        );
+
+        let program = "infixr 7 <^> (a,b) = -a - b*b\nprefix ~ (x) =-x".parse::<Program>();
+        let decls: Vec<_> = program
+            .unwrap()
+            .stmts
+            .iter()
+            .map(|stmt| match stmt {
+                Stmt::OpDecl(decl) => PrettyPrinter::new().width(28).print_decl(decl),
+                Stmt::Expr(_) => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            decls,
+            ["infixr 7 <^> (a, b) = -a -\n    b * b", "prefix ~ (x) = -x"]
+        );
+    }
+
+    #[test]
+    fn test_printer_table() {
+        let mut table = OperatorTable::default();
+        table.add_infix(TokenKind::Minus, BinOpKind::Sub, 6, Assoc::Right);
+        table.add_infix(TokenKind::Slash, BinOpKind::Div, 7, Assoc::None);
+        let config = ParseConfig::new().table(table.clone());
+        let printer = PrettyPrinter::new().table(table);
+        for (src, expected) in &[
+            ("1 - (2 - 3)", "1 - 2 - 3"),
+            ("(1 - 2) - 3", "(1 - 2) - 3"),
+            ("(1 / 2) / 3", "(1 / 2) / 3"),
+            ("(1 / 2) * 3", "(1 / 2) * 3"),
+        ] {
+            let ast = parse_tokens_with(Lexer::new(src), &config, None).unwrap();
+            assert_eq!(printer.print(&ast), *expected);
+        }
+    }
+
+    #[test]
+    fn test_printer_quantities() {
+        for (src, expected) in &[
+            ("3 m", "3 m"),
+            ("2.5 km/h", "2.5 km / h"),
+            ("2 * 3 m", "2 * 3 m"),
+            ("1 / 2 m", "1 / 2 m"),
+            ("(3 m) * 2", "3 m * 2"),
+            ("-3 m in km", "-3 m in km"),
+            ("abs 2 m", "abs 2 m"),
+            ("3 * m", "3 * m"),
+        ] {
+            assert_eq!(pretty(src, 80), *expected);
+            assert_eq!(pretty(expected, 80), *expected);
+        }
+        // a quantity is not broken, the line break would end the statement
+        assert_eq!(pretty("1000 km", 4), "1000 km");
+
+        // juxtapositions not ending with a number need the `*` to parse back
+        let config = ParseConfig::new().implicit_mul(true);
+        for (src, expected) in &[
+            ("(1 + 2) m", "(1 + 2) * m"),
+            ("-(3) m", "-3 m"),
+            ("-(1 + 3) m", "-(1 + 3) * m"),
+            ("2 x m", "2 * x * m"),
+        ] {
+            let ast = parse_tokens_with(Lexer::new(src), &config, None).unwrap();
+            assert_eq!(PrettyPrinter::new().print(&ast), *expected);
+        }
+    }
+
+    #[test]
+    fn test_printer_deep() {
+        let src = format!("{}1", "1 + ".repeat(100_000));
+        let ast = src.parse::<Ast>().unwrap();
+        assert_eq!(PrettyPrinter::new().print_flat(&ast), src);
+        let printed = PrettyPrinter::new().print(&ast);
+        assert_eq!(printed.lines().count(), 100_001);
+        assert!(printed.ends_with(" +\n    1 +\n    1"));
+
+        let mut table = OperatorTable::default();
+        let bang = TokenKind::Op("!".into());
+        table.add_postfix(bang, UniOpKind::Custom("!".to_string()), POSTFIX_BP);
+        let config = ParseConfig::new().table(table.clone());
+        let src = format!("(1 + 2){}", "!".repeat(100_000));
+        let ast = parse_tokens_with(Lexer::new(&src), &config, None).unwrap();
+        let printed = PrettyPrinter::new().table(table).width(4).print(&ast);
+        assert_eq!(
+            printed,
+            format!("(\n    1 +\n        2\n){}", "!".repeat(100_000))
+        );
+    }
+}
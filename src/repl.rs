@@ -0,0 +1,480 @@
+//! Interactive read-eval-print loop
+
+use std::fs;
+use std::mem;
+use std::path::PathBuf;
+
+use super::complete::complete;
+use super::dump::{self, StderrTracer};
+use super::formatter::{self, Formatter, Notation};
+use super::highlight::SyntaxHighlighter;
+use super::interpreter::Interpreter;
+use super::lexer::Lexer;
+use super::line_editor::{Completer, Input, LineEditor};
+use super::lint;
+use super::parser::{self, parse_stmt_with, parse_tokens_with, Ast, ParseConfig, Stmt, Tracer};
+use super::rpn_compiler::RpnCompiler;
+use super::{show_trace, Opt};
+
+const HELP: &str = "\
+expressions are evaluated, or compiled to RPN in compiler mode, and their
+values can be referred to as `_` or `ans` (the last one) and `$n` (the nth one)
+unfinished input, e.g. with an unclosed `(`, continues on the next line until
+it is complete or Ctrl-C is pressed
+numbers followed by SI units are quantities, e.g. `2.5 km/h`, which `in`
+converts, e.g. `x in m/s`
+numbers followed by `i` or `j` are imaginary, e.g. `1 + 2.5i`, and `re`, `im`,
+`abs`, `arg` and `conj` apply to complex numbers, e.g. `abs(3 + 4i)`
+
+  infixl|infixr|infix LEVEL OP (a, b) = EXPR
+  prefix|postfix OP (x) = EXPR
+                      declare an operator
+
+commands:
+  :help               show this message
+  :ast EXPR           print the syntax tree of EXPR
+  :rpn EXPR           print EXPR compiled to RPN
+  :tokens EXPR        print the tokens of EXPR
+  :mode compiler|interp
+                      compile or evaluate the following expressions
+  :vars               list the results so far, `$1` first
+  :ops                list the declared operators
+  :reset              forget the declared operators and the results
+  :load FILE          run every line of FILE
+  :obase 2|8|10|16    set the radix of the printed results
+  :notation auto|fixed:N|sci:N
+                      set the notation of the printed non-integer results,
+                      with N digits after the decimal point
+  :quit               exit";
+
+/// Names of the commands, see `HELP`
+const COMMANDS: &[&str] = &[
+    "help", "ast", "rpn", "tokens", "mode", "vars", "ops", "reset", "load", "obase", "notation",
+    "quit",
+];
+
+/// What is done with the expressions entered
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Interp,
+    Compiler,
+}
+
+/// Whether to keep reading lines after one has been handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    Continue,
+    /// The input ended in the middle of an expression
+    Incomplete,
+    Quit,
+}
+
+/// State of a REPL session
+struct Repl<'a> {
+    opt: &'a Opt,
+    mode: Mode,
+    interp: Interpreter,
+    compiler: RpnCompiler,
+    formatter: Formatter,
+    config: ParseConfig,
+    /// Source of the operator declarations, in order
+    decls: Vec<String>,
+    /// Lines of an unfinished expression
+    pending: String,
+    /// Files being run by `:load`, the innermost last
+    loading: Vec<PathBuf>,
+}
+
+pub fn run(opt: &Opt) -> i32 {
+    let mut repl = Repl::new(opt);
+    let mut editor = LineEditor::new().highlighter(Box::new(SyntaxHighlighter));
+
+    loop {
+        match editor.read_line(repl.prompt(), &repl) {
+            Ok(Input::Line(line)) => {
+                if repl.feed(&line) == Flow::Quit {
+                    break;
+                }
+            }
+            Ok(Input::Interrupted) => repl.pending.clear(),
+            Ok(Input::Eof) => {
+                repl.finish();
+                break;
+            }
+            Err(err) => {
+                show_trace(err);
+                return 1;
+            }
+        }
+    }
+
+    0
+}
+
+impl<'a> Repl<'a> {
+    fn new(opt: &'a Opt) -> Self {
+        Repl {
+            opt,
+            mode: if opt.use_compiler {
+                Mode::Compiler
+            } else {
+                Mode::Interp
+            },
+            interp: Interpreter::new(),
+            compiler: RpnCompiler::new(),
+            formatter: Formatter::new()
+                .radix(opt.obase)
+                .grouping(opt.group)
+                .notation(opt.notation),
+            config: ParseConfig::new().implicit_mul(opt.implicit_mul),
+            decls: Vec::new(),
+            pending: String::new(),
+            loading: Vec::new(),
+        }
+    }
+
+    fn prompt(&self) -> &'static str {
+        if self.pending.is_empty() {
+            "> "
+        } else {
+            "... "
+        }
+    }
+
+    /// Handle `line` together with the pending lines, keeping them pending
+    /// while they form an unfinished expression
+    fn feed(&mut self, line: &str) -> Flow {
+        if self.pending.is_empty() && line.trim_start().starts_with(':') {
+            return self.run_command(line.trim_start());
+        }
+        if !self.pending.is_empty() {
+            self.pending.push('\n');
+        }
+        self.pending.push_str(line);
+        let src = mem::take(&mut self.pending);
+        match self.handle_line(&src, true) {
+            Flow::Incomplete => {
+                self.pending = src;
+                Flow::Continue
+            }
+            flow => flow,
+        }
+    }
+
+    /// Handle the pending lines at the end of input
+    fn finish(&mut self) {
+        let src = mem::take(&mut self.pending);
+        if !src.is_empty() {
+            self.handle_line(&src, false);
+        }
+    }
+
+    /// Handle a statement, or report `Flow::Incomplete` if it is unfinished
+    /// and `more` lines may follow
+    fn handle_line(&mut self, line: &str, more: bool) -> Flow {
+        // blank lines and lone comments
+        if Lexer::new(line).next().is_none() {
+            return Flow::Continue;
+        }
+        let ast = match self.parse_line(line, more) {
+            Ok(Stmt::Expr(ast)) => {
+                show_lints(&ast, line);
+                ast
+            }
+            Ok(Stmt::OpDecl(decl)) => {
+                show_lints(&decl.body, line);
+                match self.config.declare(&decl) {
+                    Ok(()) => {
+                        self.interp.define(&decl);
+                        self.decls.push(line.trim().to_string());
+                    }
+                    Err(err) => {
+                        let err = parser::Error::from(err);
+                        err.show_diagnostic(line);
+                        show_trace(err);
+                    }
+                }
+                return Flow::Continue;
+            }
+            Err(ref err) if more && err.is_incomplete() => return Flow::Incomplete,
+            Err(err) => {
+                err.show_diagnostic(line);
+                show_trace(err);
+                return Flow::Continue;
+            }
+        };
+
+        if let Some(emit) = self.opt.emit {
+            println!("{}", emit.emit(&ast).trim_end());
+            return Flow::Continue;
+        }
+        match self.mode {
+            // the value is still recorded, so the output is prefixed with the
+            // reference to it unless the evaluation fails, which is reported
+            // as in interpreter mode
+            Mode::Compiler => {
+                let rpn = self.compiler.compile(&ast);
+                match self.interp.eval(&ast) {
+                    Ok(n) => println!("${}: {}", self.interp.record(n), rpn),
+                    Err(err) => {
+                        println!("{}", rpn);
+                        err.show_diagnostic(line);
+                        show_trace(err);
+                    }
+                }
+            }
+            Mode::Interp => match self.interp.eval(&ast) {
+                Ok(value) => {
+                    println!("{}", self.formatter.format_value(&value));
+                    self.interp.record(value);
+                }
+                Err(err) => {
+                    err.show_diagnostic(line);
+                    show_trace(err);
+                }
+            },
+        }
+        Flow::Continue
+    }
+
+    /// Parse `line`, dumping it as requested by the options unless it is
+    /// unfinished and `more` lines may follow
+    fn parse_line(&self, line: &str, more: bool) -> Result<Stmt, parser::Error> {
+        let mut tracer = StderrTracer::default();
+        let tracer = if self.opt.trace_parse {
+            Some(&mut tracer as &mut dyn Tracer)
+        } else {
+            None
+        };
+        let stmt = if self.opt.dump_tokens {
+            let tokens = Lexer::new(line).lex()?;
+            let stmt = parse_stmt_with(tokens.iter().cloned().map(Ok), &self.config, tracer);
+            if !(more && stmt.as_ref().is_err_and(parser::Error::is_incomplete)) {
+                print!("{}", dump::dump_tokens(&tokens));
+            }
+            stmt?
+        } else {
+            parse_stmt_with(Lexer::new(line), &self.config, tracer)?
+        };
+        if self.opt.dump_ast {
+            match stmt {
+                Stmt::Expr(ref ast) => print!("{}", dump::dump_ast(ast)),
+                Stmt::OpDecl(ref decl) => print!("{}", dump::dump_ast(&decl.body)),
+            }
+        }
+        Ok(stmt)
+    }
+
+    /// Parse the argument of a command as an expression
+    fn parse_arg(&self, arg: &str) -> Option<Ast> {
+        match parse_tokens_with(Lexer::new(arg), &self.config, None) {
+            Ok(ast) => Some(ast),
+            Err(err) => {
+                err.show_diagnostic(arg);
+                show_trace(err);
+                None
+            }
+        }
+    }
+
+    /// Lines of `:vars`, e.g. `$1 = 6`
+    fn results(&self) -> Vec<String> {
+        self.interp
+            .results()
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("${} = {}", i + 1, self.formatter.format_value(value)))
+            .collect()
+    }
+
+    /// Run a REPL command, i.e. a line starting with `:`
+    fn run_command(&mut self, line: &str) -> Flow {
+        let line = line[1..].trim_start();
+        let (name, arg) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim_start()),
+            None => (line, ""),
+        };
+        match name {
+            "help" => println!("{}", HELP),
+            "tokens" => match Lexer::new(arg).lex() {
+                Ok(tokens) => print!("{}", dump::dump_tokens(&tokens)),
+                Err(err) => {
+                    let err = parser::Error::from(err);
+                    err.show_diagnostic(arg);
+                    show_trace(err);
+                }
+            },
+            "ast" => {
+                if let Some(ast) = self.parse_arg(arg) {
+                    print!("{}", dump::dump_ast(&ast));
+                }
+            }
+            "rpn" => {
+                if let Some(ast) = self.parse_arg(arg) {
+                    println!("{}", self.compiler.compile(&ast));
+                }
+            }
+            "mode" => match arg {
+                "compiler" => self.mode = Mode::Compiler,
+                "interp" => self.mode = Mode::Interp,
+                "" => match self.mode {
+                    Mode::Compiler => println!("compiler"),
+                    Mode::Interp => println!("interp"),
+                },
+                _ => eprintln!("usage: :mode compiler|interp"),
+            },
+            "vars" => {
+                for line in self.results() {
+                    println!("{}", line);
+                }
+            }
+            "ops" => {
+                for decl in &self.decls {
+                    println!("{}", decl);
+                }
+            }
+            "reset" => {
+                self.interp = Interpreter::new();
+                self.config = ParseConfig::new().implicit_mul(self.opt.implicit_mul);
+                self.decls.clear();
+            }
+            "load" => return self.load(arg),
+            "obase" => match arg.parse() {
+                Ok(radix) if formatter::is_supported_radix(radix) => {
+                    self.formatter = self.formatter.radix(radix);
+                }
+                _ => eprintln!("usage: :obase 2|8|10|16"),
+            },
+            "notation" => match arg.parse::<Notation>() {
+                Ok(notation) => self.formatter = self.formatter.notation(notation),
+                Err(_) => eprintln!("usage: :notation auto|fixed:N|sci:N"),
+            },
+            "quit" => return Flow::Quit,
+            _ => eprintln!("unknown command ':{}', see :help", name),
+        }
+        Flow::Continue
+    }
+
+    /// Handle every non-blank line of the file at `path` as if it was entered
+    fn load(&mut self, path: &str) -> Flow {
+        if path.is_empty() {
+            eprintln!("usage: :load FILE");
+            return Flow::Continue;
+        }
+        let src = match fs::read_to_string(path) {
+            Ok(src) => src,
+            Err(err) => {
+                eprintln!("cannot read '{}': {}", path, err);
+                return Flow::Continue;
+            }
+        };
+        // a file loading itself, directly or not, would never finish
+        let file = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        if self.loading.contains(&file) {
+            eprintln!("cannot load '{}': it is already being loaded", path);
+            return Flow::Continue;
+        }
+        self.loading.push(file);
+        let mut flow = Flow::Continue;
+        for line in src.lines() {
+            if self.feed(line) == Flow::Quit {
+                flow = Flow::Quit;
+                break;
+            }
+        }
+        if flow == Flow::Continue {
+            self.finish();
+        }
+        self.loading.pop();
+        flow
+    }
+}
+
+impl<'a> Completer for Repl<'a> {
+    fn complete(&self, line: &str, cursor: usize) -> (usize, Vec<String>) {
+        let completion = complete(line, cursor, &self.interp, COMMANDS);
+        (completion.start, completion.candidates)
+    }
+}
+
+fn show_lints(ast: &Ast, input: &str) {
+    for lint in lint::lint(ast) {
+        lint.show_diagnostic(input);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Value;
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_commands() {
+        let opt = Opt::from_iter(&["myparse"]);
+        let mut repl = Repl::new(&opt);
+        assert_eq!(repl.feed(":mode compiler"), Flow::Continue);
+        assert_eq!(repl.mode, Mode::Compiler);
+        repl.feed("infixl 6 <+> (a, b) = a + 2*b");
+        repl.feed("1 <+> 2");
+        assert_eq!(repl.decls, vec!["infixl 6 <+> (a, b) = a + 2*b"]);
+        repl.feed(":mode interp");
+        repl.feed("_ * 2");
+        assert_eq!(repl.results(), vec!["$1 = 5", "$2 = 10"]);
+        repl.feed(":notation fixed:2");
+        repl.feed("1.0 / 4");
+        assert_eq!(repl.results()[2], "$3 = 0.25");
+        repl.feed(":notation sci");
+        assert_eq!(
+            repl.formatter,
+            Formatter::new().notation(Notation::Fixed(2))
+        );
+        repl.feed(" :reset");
+        assert!(repl.decls.is_empty());
+        assert!(repl.parse_line("1 <+> 2", false).is_err());
+        assert_eq!(repl.feed(":quit"), Flow::Quit);
+    }
+
+    #[test]
+    fn test_continuation() {
+        let opt = Opt::from_iter(&["myparse"]);
+        let mut repl = Repl::new(&opt);
+        for line in &[
+            "infixl 6 <+> (a,",
+            "b) =",
+            "a * b",
+            "(1 <+>",
+            "/* 2 */ 3) + 4",
+        ] {
+            repl.feed(line);
+        }
+        assert_eq!(repl.decls, vec!["infixl 6 <+> (a,\nb) =\na * b"]);
+        assert_eq!(repl.interp.eval(&"_".parse().unwrap()), Ok(Value::Int(7)));
+        repl.feed("1 + /*");
+        assert_eq!(repl.prompt(), "... ");
+        repl.feed("*/ (2");
+        repl.finish();
+        assert!(repl.pending.is_empty());
+        assert_eq!(repl.interp.eval(&"_".parse().unwrap()), Ok(Value::Int(7)));
+    }
+
+    #[test]
+    fn test_load_cycle() {
+        let dir = std::env::temp_dir().join(format!("myparse-load-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.txt");
+        let b = dir.join("b.txt");
+        fs::write(&a, format!("1 + 1\n:load {}\n", b.display())).unwrap();
+        fs::write(&b, format!("2 * 3\n:load {}\n4\n", a.display())).unwrap();
+
+        let opt = Opt::from_iter(&["myparse"]);
+        let mut repl = Repl::new(&opt);
+        assert_eq!(repl.feed(&format!(":load {}", a.display())), Flow::Continue);
+        assert_eq!(repl.results(), vec!["$1 = 2", "$2 = 6", "$3 = 4"]);
+        assert!(repl.loading.is_empty());
+        // the file can be loaded again once it is done
+        repl.feed(&format!(":load {}", b.display()));
+        assert_eq!(repl.results().len(), 6);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
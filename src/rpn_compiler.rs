@@ -1,55 +1,117 @@
-use super::parser::{Ast, BinOp, UniOp};
+use std::mem;
 
-pub struct RpnCompiler;
+use super::parser::{Ast, AstNode, BinOp, UniOp, UniOpKind};
+use super::visit::{Step, Walk};
+
+pub struct RpnCompiler {
+    buf: String,
+}
 
 impl RpnCompiler {
     pub fn new() -> Self {
-        RpnCompiler
+        RpnCompiler { buf: String::new() }
     }
 
+    /// Compile `expr`, walking it with an explicit stack so that any depth
+    /// is fine
     pub fn compile(&mut self, expr: &Ast) -> String {
-        let mut buf = String::new();
-        self.compile_inner(expr, &mut buf);
-        buf
-    }
-
-    fn compile_inner(&mut self, expr: &Ast, buf: &mut String) {
-        use super::parser::AstNode::*;
-        match expr.value {
-            Num(n) => buf.push_str(&n.to_string()),
-            UniOp { ref op, ref e } => {
-                self.compile_uniop(op, buf);
-                self.compile_inner(e, buf);
-            }
-            BinOp {
-                ref op,
-                ref l,
-                ref r,
-            } => {
-                self.compile_inner(l, buf);
-                buf.push_str(" ");
-                self.compile_inner(r, buf);
-                buf.push_str(" ");
-                self.compile_binop(op, buf);
+        let mut walk = Walk::new(expr);
+        while let Some(step) = walk.next() {
+            match step {
+                Step::Enter(ast) => {
+                    if let Some(item) = compile_leaf(ast) {
+                        self.item(&item);
+                        walk.skip_children();
+                    }
+                }
+                Step::Leave(ast) => match ast.value {
+                    AstNode::UniOp { ref op, ref e } if signed_number(op, e).is_none() => {
+                        self.compile_uniop(op)
+                    }
+                    AstNode::BinOp { ref op, .. } => self.compile_binop(op),
+                    _ => {}
+                },
             }
         }
+        mem::take(&mut self.buf)
     }
 
-    fn compile_uniop(&mut self, op: &UniOp, buf: &mut String) {
+    /// Append `item`, separated from the previous one
+    fn item(&mut self, item: &str) {
+        if !self.buf.is_empty() {
+            self.buf.push(' ');
+        }
+        self.buf.push_str(item);
+    }
+
+    /// Compile a unary operator applied to the operand compiled before it
+    fn compile_uniop(&mut self, op: &UniOp) {
         use super::parser::UniOpKind::*;
         match op.value {
-            Plus => buf.push_str("+"),
-            Minus => buf.push_str("-"),
+            Plus => {}
+            Minus => self.item("neg"),
+            Func(func) => self.item(func.name()),
+            Custom(ref symbol) => self.item(symbol),
         }
     }
 
-    fn compile_binop(&mut self, op: &BinOp, buf: &mut String) {
+    fn compile_binop(&mut self, op: &BinOp) {
         use super::parser::BinOpKind::*;
         match op.value {
-            Add => buf.push_str("+"),
-            Sub => buf.push_str("-"),
-            Mul => buf.push_str("*"),
-            Div => buf.push_str("/"),
+            Add => self.item("+"),
+            Sub => self.item("-"),
+            Mul => self.item("*"),
+            Div => self.item("/"),
+            Convert => self.item("in"),
+            Custom(ref symbol) => self.item(symbol),
         }
     }
 }
+
+/// The item for a node compiled as a whole, without its children
+fn compile_leaf(ast: &Ast) -> Option<String> {
+    match ast.value {
+        AstNode::Num(n) => Some(n.to_string()),
+        AstNode::Decimal(d) => Some(d.to_string()),
+        AstNode::Imaginary(d) => Some(format!("{}i", d)),
+        AstNode::Var(ref name) => Some(name.clone()),
+        AstNode::UniOp { ref op, ref e } => signed_number(op, e),
+        AstNode::BinOp { .. } => None,
+    }
+}
+
+/// A sign applied directly to a number is written as part of the number,
+/// e.g. `-10`
+fn signed_number(op: &UniOp, e: &Ast) -> Option<String> {
+    let sign = match op.value {
+        UniOpKind::Plus => '+',
+        UniOpKind::Minus => '-',
+        _ => return None,
+    };
+    match e.value {
+        AstNode::Num(n) => Some(format!("{}{}", sign, n)),
+        AstNode::Decimal(d) => Some(format!("{}{}", sign, d)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rpn_compiler() {
+        let mut compiler = RpnCompiler::new();
+        let mut compile = |s: &str| compiler.compile(&s.parse().unwrap());
+        assert_eq!(compile("1 + 2 * 3 - -10"), "1 2 3 * + -10 -");
+        assert_eq!(compile("- -1"), "-1 neg");
+        assert_eq!(compile("-(1 + 2) * +3"), "1 2 + neg +3 *");
+        assert_eq!(compile("--(2)"), "-2 neg");
+        assert_eq!(compile("+ +1"), "+1");
+
+        let deep = format!("{}1", "1+".repeat(100_000));
+        let rpn = compile(&deep);
+        assert!(rpn.starts_with("1 1 + 1 + "));
+        assert_eq!(rpn.len(), 400_001);
+    }
+}
@@ -0,0 +1,138 @@
+//! Execution of script files, see `parser::parse_program`
+
+use std::fs;
+use std::path::Path;
+
+use super::formatter::Formatter;
+use super::interpreter::{Interpreter, InterpreterError};
+use super::lexer::Loc;
+use super::lint;
+use super::parser::{line_col, parse_program, ParseConfig, Stmt};
+use super::rpn_compiler::RpnCompiler;
+use super::{show_trace, Opt};
+
+/// State shared by the statements of a script
+struct Script<'a> {
+    opt: &'a Opt,
+    interp: Interpreter,
+    compiler: RpnCompiler,
+    formatter: Formatter,
+}
+
+impl<'a> Script<'a> {
+    fn new(opt: &'a Opt) -> Self {
+        Script {
+            opt,
+            interp: Interpreter::new(),
+            compiler: RpnCompiler::new(),
+            formatter: Formatter::new()
+                .radix(opt.obase)
+                .grouping(opt.group)
+                .notation(opt.notation),
+        }
+    }
+
+    /// Run `stmt`, returning the line to print for an expression
+    fn exec(&mut self, stmt: &Stmt) -> Result<Option<String>, InterpreterError> {
+        let ast = match stmt {
+            Stmt::Expr(ast) => ast,
+            Stmt::OpDecl(decl) => {
+                self.interp.define(decl);
+                return Ok(None);
+            }
+        };
+        if let Some(emit) = self.opt.emit {
+            return Ok(Some(emit.emit(ast).trim_end().to_string()));
+        }
+        if self.opt.use_compiler {
+            return Ok(Some(self.compiler.compile(ast)));
+        }
+        let value = self.interp.eval(ast)?;
+        let line = self.formatter.format_value(&value);
+        self.interp.record(value);
+        Ok(Some(line))
+    }
+}
+
+/// Print the position of `loc` in the script, `None` being its end
+fn show_position(path: &Path, src: &str, loc: Option<&Loc>) {
+    let (line, col) = line_col(src, loc.map_or(src.len(), |loc| loc.0));
+    eprintln!("{}:{}:{}:", path.display(), line, col);
+}
+
+/// Entry point of the `run` subcommand.
+///
+/// The whole script is parsed before any statement runs, and it stops at the
+/// first error.
+pub fn run(path: &Path, opt: &Opt) -> i32 {
+    let src = match fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            return 1;
+        }
+    };
+    let config = ParseConfig::new().implicit_mul(opt.implicit_mul);
+    let program = match parse_program(&src, &config) {
+        Ok(program) => program,
+        Err(err) => {
+            show_position(path, &src, err.loc());
+            err.show_diagnostic(&src);
+            show_trace(err);
+            return 1;
+        }
+    };
+
+    let mut script = Script::new(opt);
+    for stmt in &program.stmts {
+        let ast = match stmt {
+            Stmt::Expr(ast) => ast,
+            Stmt::OpDecl(decl) => &decl.body,
+        };
+        for lint in lint::lint(ast) {
+            show_position(path, &src, Some(&lint.loc));
+            lint.show_diagnostic(&src);
+        }
+        match script.exec(stmt) {
+            Ok(Some(line)) => println!("{}", line),
+            Ok(None) => {}
+            Err(err) => {
+                show_position(path, &src, Some(&err.loc));
+                err.show_diagnostic(&src);
+                show_trace(err);
+                return 1;
+            }
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Program;
+    use structopt::StructOpt;
+
+    #[test]
+    fn test_exec() {
+        let opt = Opt::from_iter(&["myparse", "run", "script.calc"]);
+        let mut script = Script::new(&opt);
+        let program: Program = "infixl 6 <+> (a, b) = a * b\n2 <+> 3; _ + 1\n$1 / 0"
+            .parse()
+            .unwrap();
+        let ret: Vec<_> = program
+            .stmts
+            .iter()
+            .map(|stmt| script.exec(stmt).map_err(|err| err.loc))
+            .collect();
+        assert_eq!(
+            ret,
+            [
+                Ok(None),
+                Ok(Some("6".to_string())),
+                Ok(Some("7".to_string())),
+                Err(Loc(43, 49))
+            ]
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::lexer::{Lexer, Loc};
+use super::parser::{parse_program, print_annot, Error, ParseConfig, Stmt};
+use super::printer::PrettyPrinter;
+use super::show_trace;
+
+/// Outcome of formatting a single file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Unchanged,
+    Changed,
+    Failed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum FormatError {
+    Syntax(Error),
+    /// A comment between the tokens of a statement, which would be lost
+    InnerComment(Loc),
+}
+
+impl FormatError {
+    pub fn show_diagnostic(&self, input: &str) {
+        match self {
+            FormatError::Syntax(err) => err.show_diagnostic(input),
+            FormatError::InnerComment(loc) => {
+                eprintln!("{}", self);
+                print_annot(input, loc);
+            }
+        }
+    }
+}
+
+impl<E: Into<Error>> From<E> for FormatError {
+    fn from(e: E) -> Self {
+        FormatError::Syntax(e.into())
+    }
+}
+
+impl std::error::Error for FormatError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FormatError::Syntax(err) => Some(err),
+            FormatError::InnerComment(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::Syntax(_) => write!(f, "cannot format"),
+            FormatError::InnerComment(loc) => write!(
+                f,
+                "{}: comments inside a statement are not supported by the formatter",
+                loc
+            ),
+        }
+    }
+}
+
+/// Format a program source in canonical style, one statement per line.
+///
+/// Comments between statements are kept, as well as single blank lines. A
+/// comment which followed a statement on its line still does. Literals keep
+/// their spelling, e.g. `0xFF` or `1_000`.
+pub fn format_source(printer: &PrettyPrinter, src: &str) -> Result<String, FormatError> {
+    let mut lexer = Lexer::new(src);
+    lexer.by_ref().collect::<Result<Vec<_>, _>>()?;
+    let comments = lexer.comments();
+    let program = parse_program(src, &ParseConfig::new())?;
+    let inner = comments
+        .iter()
+        .find(|c| program.locs.iter().any(|loc| loc.0 < c.0 && c.0 < loc.1));
+    if let Some(loc) = inner {
+        return Err(FormatError::InnerComment(loc.clone()));
+    }
+
+    let mut out = String::new();
+    let mut comments = comments.iter().peekable();
+    // end of the last statement or comment written
+    let mut last = 0;
+    let mut config = ParseConfig::new();
+    let printer = printer.clone().source(src);
+    for (stmt, loc) in program.stmts.iter().zip(&program.locs) {
+        while let Some(comment) = comments.next_if(|c| c.0 < loc.0) {
+            write_comment(&mut out, src, last, comment);
+            last = comment.1;
+        }
+        separate_line(&mut out, &src[last..loc.0]);
+        let printer = printer.clone().table(config.operators().clone());
+        match stmt {
+            Stmt::Expr(ast) => out.push_str(&printer.print(ast)),
+            Stmt::OpDecl(decl) => {
+                out.push_str(&printer.print_decl(decl));
+                config.declare(decl)?;
+            }
+        }
+        last = loc.1;
+    }
+    for comment in comments {
+        write_comment(&mut out, src, last, comment);
+        last = comment.1;
+    }
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Write `comment`, which follows the statement or comment ending at `last`
+fn write_comment(out: &mut String, src: &str, last: usize, comment: &Loc) {
+    let gap = &src[last..comment.0];
+    if out.is_empty() || gap.contains('\n') {
+        separate_line(out, gap);
+    } else {
+        out.push(' ');
+    }
+    out.push_str(&src[comment.0..comment.1]);
+}
+
+/// Start a new line unless `out` is empty, keeping a blank line if `gap`, the
+/// source since the last thing written, has one
+fn separate_line(out: &mut String, gap: &str) {
+    if out.is_empty() {
+        return;
+    }
+    out.push('\n');
+    if gap.matches('\n').count() > 1 {
+        out.push('\n');
+    }
+}
+
+fn format_file(printer: &PrettyPrinter, path: &Path, check: bool) -> Status {
+    let src = match fs::read_to_string(path) {
+        Ok(src) => src,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            return Status::Failed;
+        }
+    };
+
+    let formatted = match format_source(printer, &src) {
+        Ok(formatted) => formatted,
+        Err(err) => {
+            eprintln!("{}:", path.display());
+            err.show_diagnostic(&src);
+            show_trace(err);
+            return Status::Failed;
+        }
+    };
+    if formatted == src {
+        return Status::Unchanged;
+    }
+
+    if check {
+        eprintln!("would reformat {}", path.display());
+    } else if let Err(err) = fs::write(path, formatted) {
+        eprintln!("{}: {}", path.display(), err);
+        return Status::Failed;
+    }
+    Status::Changed
+}
+
+/// Entry point of the `fmt` subcommand.
+///
+/// Files that fail to lex or parse are left untouched. In `check` mode no file
+/// is written and the exit code is non-zero if any file would change.
+pub fn run(files: &[PathBuf], check: bool, width: usize) -> i32 {
+    let printer = PrettyPrinter::new().width(width);
+    let mut code = 0;
+    for path in files {
+        match format_file(&printer, path, check) {
+            Status::Unchanged => {}
+            Status::Changed if !check => {}
+            Status::Changed => code = 1,
+            Status::Failed => code = 1,
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formatter::Formatter;
+    use crate::interpreter::Interpreter;
+
+    /// Lines printed by `run` for `src`
+    fn run(src: &str) -> Vec<String> {
+        let mut interp = Interpreter::new();
+        let program = parse_program(src, &ParseConfig::new()).unwrap();
+        let mut lines = Vec::new();
+        for stmt in &program.stmts {
+            match stmt {
+                Stmt::OpDecl(decl) => interp.define(decl),
+                Stmt::Expr(ast) => {
+                    let value = interp.eval(ast).unwrap();
+                    lines.push(Formatter::new().format_value(&value));
+                    interp.record(value);
+                }
+            }
+        }
+        lines
+    }
+
+    #[test]
+    fn test_format_source() {
+        let printer = PrettyPrinter::new();
+        assert_eq!(
+            format_source(&printer, "# head\n\n(1+2)*3 // tail\n"),
+            Ok("# head\n\n(1 + 2) * 3 // tail\n".to_string())
+        );
+        assert_eq!(
+            format_source(&printer, "1 + /* x */ 2"),
+            Err(FormatError::InnerComment(Loc(4, 11)))
+        );
+
+        let src = format!("{}1", "1+".repeat(2_000));
+        let formatted = format_source(&printer, &src).unwrap();
+        assert_eq!(formatted.lines().count(), 2_001);
+        assert_eq!(run(&formatted), vec!["2001"]);
+    }
+
+    #[test]
+    fn test_format_program() {
+        let src = "# sums\n111 + 222 * 333 - 444; 1 - 2 // two\n\n\n\
+                   infixl 6 <+> (a, b) = a * b - a * b * b\n(1 <+> 2) <+> 3\n-_\n";
+        let printer = PrettyPrinter::new().width(16);
+        let formatted = format_source(&printer, src).unwrap();
+        assert_eq!(
+            formatted,
+            "# sums\n111 +\n    222 * 333 -\n    444\n1 - 2 // two\n\n\
+             infixl 6 <+> (a, b) = a *\n    b -\n    a * b * b\n1 <+> 2 <+> 3\n-_\n"
+        );
+        assert_eq!(run(&formatted), run(src));
+        assert_eq!(format_source(&printer, &formatted), Ok(formatted));
+
+        assert_eq!(
+            format_source(&printer, "0xFF+1_000_000\n-(0b1) * 2.50j"),
+            Ok("0xFF + 1_000_000\n-0b1 * 2.50j\n".to_string())
+        );
+        assert_eq!(
+            format_source(&printer, "3 m*2\n2.50 km/h"),
+            Ok("3 m * 2\n2.50 km / h\n".to_string())
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Units of measure and dimensional analysis
+//!
+//! A unit is a factor to the SI base units and a `Dimension`, the exponents of
+//! those base units. The SI units also take the prefixes from `y` (1e-24) to
+//! `Y` (1e24), `u` standing for micro.
+//!
+//! ```
+//! use myparse::units::{lookup, Dimension};
+//!
+//! let km = lookup("km").unwrap();
+//! let h = lookup("h").unwrap();
+//! assert_eq!(km.factor / h.factor, 1000.0 / 3600.0);
+//! assert_eq!(km.dim.checked_div(h.dim).unwrap().to_string(), "m/s");
+//! assert_eq!(Dimension::NONE.to_string(), "1");
+//! ```
+
+use std::fmt;
+
+/// Symbols of the SI base units, in the order of the exponents of a
+/// `Dimension`
+const BASE_UNITS: [&str; 7] = ["m", "kg", "s", "A", "K", "mol", "cd"];
+
+/// Symbol, factor, dimension and whether the SI prefixes apply
+const UNITS: &[(&str, f64, [i8; 7], bool)] = &[
+    ("m", 1.0, [1, 0, 0, 0, 0, 0, 0], true),
+    ("g", 1e-3, [0, 1, 0, 0, 0, 0, 0], true),
+    ("s", 1.0, [0, 0, 1, 0, 0, 0, 0], true),
+    ("A", 1.0, [0, 0, 0, 1, 0, 0, 0], true),
+    ("K", 1.0, [0, 0, 0, 0, 1, 0, 0], true),
+    ("mol", 1.0, [0, 0, 0, 0, 0, 1, 0], true),
+    ("cd", 1.0, [0, 0, 0, 0, 0, 0, 1], true),
+    ("Hz", 1.0, [0, 0, -1, 0, 0, 0, 0], true),
+    ("N", 1.0, [1, 1, -2, 0, 0, 0, 0], true),
+    ("Pa", 1.0, [-1, 1, -2, 0, 0, 0, 0], true),
+    ("J", 1.0, [2, 1, -2, 0, 0, 0, 0], true),
+    ("W", 1.0, [2, 1, -3, 0, 0, 0, 0], true),
+    ("C", 1.0, [0, 0, 1, 1, 0, 0, 0], true),
+    ("V", 1.0, [2, 1, -3, -1, 0, 0, 0], true),
+    ("ohm", 1.0, [2, 1, -3, -2, 0, 0, 0], true),
+    ("F", 1.0, [-2, -1, 4, 2, 0, 0, 0], true),
+    ("T", 1.0, [0, 1, -2, -1, 0, 0, 0], true),
+    ("L", 1e-3, [3, 0, 0, 0, 0, 0, 0], true),
+    ("min", 60.0, [0, 0, 1, 0, 0, 0, 0], false),
+    ("h", 3600.0, [0, 0, 1, 0, 0, 0, 0], false),
+];
+
+const PREFIXES: &[(&str, f64)] = &[
+    ("Y", 1e24),
+    ("Z", 1e21),
+    ("E", 1e18),
+    ("P", 1e15),
+    ("T", 1e12),
+    ("G", 1e9),
+    ("M", 1e6),
+    ("k", 1e3),
+    ("h", 1e2),
+    ("da", 1e1),
+    ("d", 1e-1),
+    ("c", 1e-2),
+    ("m", 1e-3),
+    ("u", 1e-6),
+    ("n", 1e-9),
+    ("p", 1e-12),
+    ("f", 1e-15),
+    ("a", 1e-18),
+    ("z", 1e-21),
+    ("y", 1e-24),
+];
+
+/// Exponents of the SI base units, in the order `m`, `kg`, `s`, `A`, `K`,
+/// `mol`, `cd`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Dimension(pub [i8; 7]);
+
+impl Dimension {
+    /// Dimension of plain numbers
+    pub const NONE: Dimension = Dimension([0; 7]);
+
+    /// Dimension of a product, or `None` if an exponent overflows
+    pub fn checked_mul(self, other: Dimension) -> Option<Dimension> {
+        self.zip_with(other, i8::checked_add)
+    }
+
+    /// Dimension of a quotient, or `None` if an exponent overflows
+    pub fn checked_div(self, other: Dimension) -> Option<Dimension> {
+        self.zip_with(other, i8::checked_sub)
+    }
+
+    /// Combine the exponents of `self` and `other` pairwise
+    fn zip_with(self, other: Dimension, f: fn(i8, i8) -> Option<i8>) -> Option<Dimension> {
+        let mut exps = self.0;
+        for (exp, other) in exps.iter_mut().zip(other.0) {
+            *exp = f(*exp, other)?;
+        }
+        Some(Dimension(exps))
+    }
+}
+
+impl fmt::Display for Dimension {
+    /// Write the dimension in base units, e.g. `m*kg/s^2`, or `1` if it has
+    /// none
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let product = |positive: bool| {
+            BASE_UNITS
+                .iter()
+                .zip(self.0)
+                .filter(|&(_, exp)| exp != 0 && (exp > 0) == positive)
+                .map(|(unit, exp)| match exp.abs() {
+                    1 => unit.to_string(),
+                    exp => format!("{}^{}", unit, exp),
+                })
+                .collect::<Vec<_>>()
+        };
+        let (num, den) = (product(true), product(false));
+        if num.is_empty() {
+            write!(f, "1")?;
+        } else {
+            write!(f, "{}", num.join("*"))?;
+        }
+        match den.len() {
+            0 => Ok(()),
+            1 => write!(f, "/{}", den[0]),
+            _ => write!(f, "/({})", den.join("*")),
+        }
+    }
+}
+
+/// Value of one unit in SI base units
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Unit {
+    pub factor: f64,
+    pub dim: Dimension,
+}
+
+/// Look up the unit `name`, possibly with an SI prefix such as the `k` of
+/// `km`
+///
+/// A name which is both a unit and a prefixed unit is the former, so `h` is
+/// an hour and `cd` a candela.
+pub fn lookup(name: &str) -> Option<Unit> {
+    let unit = |symbol: &str| UNITS.iter().find(|unit| unit.0 == symbol);
+    if let Some(&(_, factor, dim, _)) = unit(name) {
+        return Some(Unit {
+            factor,
+            dim: Dimension(dim),
+        });
+    }
+    PREFIXES
+        .iter()
+        .find_map(|&(prefix, scale)| match unit(name.strip_prefix(prefix)?) {
+            Some(&(_, factor, dim, true)) => Some(Unit {
+                factor: scale * factor,
+                dim: Dimension(dim),
+            }),
+            _ => None,
+        })
+}
+
+/// Unit written in a conversion, e.g. `km/h` in `x in km/h`
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitName {
+    pub name: String,
+    /// Value of the unit in SI base units
+    pub factor: f64,
+}
+
+/// Number with a dimension, in SI base units
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantity {
+    pub value: f64,
+    pub dim: Dimension,
+    /// Unit to print the quantity in, if it was converted to one
+    pub unit: Option<UnitName>,
+}
+
+impl Quantity {
+    pub fn new(value: f64, dim: Dimension) -> Self {
+        Quantity {
+            value,
+            dim,
+            unit: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let dim = |name| lookup(name).map(|unit| unit.dim.to_string());
+        assert_eq!(dim("N"), Some("m*kg/s^2".to_string()));
+        assert_eq!(dim("Hz"), Some("1/s".to_string()));
+        assert_eq!(dim("ohm"), Some("m^2*kg/(s^3*A^2)".to_string()));
+        assert_eq!(lookup("kg").map(|unit| unit.factor), Some(1.0));
+        assert_eq!(lookup("dam").map(|unit| unit.factor), Some(10.0));
+        assert_eq!(lookup("h").map(|unit| unit.factor), Some(3600.0));
+        assert_eq!(lookup("hPa").map(|unit| unit.factor), Some(100.0));
+        // only the SI units take prefixes
+        assert_eq!(lookup("kh"), None);
+        assert_eq!(lookup("x"), None);
+        assert_eq!(lookup("k"), None);
+        let m = Dimension([1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(Dimension([127, 0, 0, 0, 0, 0, 0]).checked_mul(m), None);
+        assert_eq!(Dimension::NONE.checked_div(m).unwrap().to_string(), "1/m");
+    }
+}
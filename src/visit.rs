@@ -0,0 +1,314 @@
+//! Traversal of `Ast`s
+//!
+//! `Visitor` walks an `Ast` by reference, `VisitorMut` by mutable reference
+//! and `Fold` by value, rebuilding the tree. Every method has a default
+//! implementation that recurses into the children through the matching
+//! `walk_*` function, so an implementation only overrides the nodes it is
+//! interested in and calls `walk_*` itself to keep descending.
+//!
+//! These recurse once per level of the tree. `Walk` iterates over the nodes
+//! with an explicit stack instead, for passes that must handle trees of any
+//! depth.
+
+use std::mem;
+
+use super::lexer::{Decimal, Loc};
+use super::parser::{Ast, AstNode, BinOp, UniOp};
+
+pub trait Visitor {
+    fn visit_ast(&mut self, ast: &Ast) {
+        walk_ast(self, ast)
+    }
+    fn visit_num(&mut self, _n: u64, _loc: &Loc) {}
+    fn visit_decimal(&mut self, _d: Decimal, _loc: &Loc) {}
+    fn visit_imaginary(&mut self, _d: Decimal, _loc: &Loc) {}
+    fn visit_var(&mut self, _name: &str, _loc: &Loc) {}
+    fn visit_uniop(&mut self, op: &UniOp, e: &Ast, loc: &Loc) {
+        walk_uniop(self, op, e, loc)
+    }
+    fn visit_binop(&mut self, op: &BinOp, l: &Ast, r: &Ast, loc: &Loc) {
+        walk_binop(self, op, l, r, loc)
+    }
+}
+
+pub fn walk_ast<V: Visitor + ?Sized>(v: &mut V, ast: &Ast) {
+    match ast.value {
+        AstNode::Num(n) => v.visit_num(n, &ast.loc),
+        AstNode::Decimal(d) => v.visit_decimal(d, &ast.loc),
+        AstNode::Imaginary(d) => v.visit_imaginary(d, &ast.loc),
+        AstNode::Var(ref name) => v.visit_var(name, &ast.loc),
+        AstNode::UniOp { ref op, ref e } => v.visit_uniop(op, e, &ast.loc),
+        AstNode::BinOp {
+            ref op,
+            ref l,
+            ref r,
+        } => v.visit_binop(op, l, r, &ast.loc),
+    }
+}
+
+pub fn walk_uniop<V: Visitor + ?Sized>(v: &mut V, _op: &UniOp, e: &Ast, _loc: &Loc) {
+    v.visit_ast(e)
+}
+
+pub fn walk_binop<V: Visitor + ?Sized>(v: &mut V, _op: &BinOp, l: &Ast, r: &Ast, _loc: &Loc) {
+    v.visit_ast(l);
+    v.visit_ast(r);
+}
+
+pub trait VisitorMut {
+    fn visit_ast_mut(&mut self, ast: &mut Ast) {
+        walk_ast_mut(self, ast)
+    }
+    fn visit_num_mut(&mut self, _n: &mut u64, _loc: &mut Loc) {}
+    fn visit_decimal_mut(&mut self, _d: &mut Decimal, _loc: &mut Loc) {}
+    fn visit_imaginary_mut(&mut self, _d: &mut Decimal, _loc: &mut Loc) {}
+    fn visit_var_mut(&mut self, _name: &mut String, _loc: &mut Loc) {}
+    fn visit_uniop_mut(&mut self, op: &mut UniOp, e: &mut Ast, loc: &mut Loc) {
+        walk_uniop_mut(self, op, e, loc)
+    }
+    fn visit_binop_mut(&mut self, op: &mut BinOp, l: &mut Ast, r: &mut Ast, loc: &mut Loc) {
+        walk_binop_mut(self, op, l, r, loc)
+    }
+}
+
+pub fn walk_ast_mut<V: VisitorMut + ?Sized>(v: &mut V, ast: &mut Ast) {
+    match ast.value {
+        AstNode::Num(ref mut n) => v.visit_num_mut(n, &mut ast.loc),
+        AstNode::Decimal(ref mut d) => v.visit_decimal_mut(d, &mut ast.loc),
+        AstNode::Imaginary(ref mut d) => v.visit_imaginary_mut(d, &mut ast.loc),
+        AstNode::Var(ref mut name) => v.visit_var_mut(name, &mut ast.loc),
+        AstNode::UniOp {
+            ref mut op,
+            ref mut e,
+        } => v.visit_uniop_mut(op, e, &mut ast.loc),
+        AstNode::BinOp {
+            ref mut op,
+            ref mut l,
+            ref mut r,
+        } => v.visit_binop_mut(op, l, r, &mut ast.loc),
+    }
+}
+
+pub fn walk_uniop_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    _op: &mut UniOp,
+    e: &mut Ast,
+    _loc: &mut Loc,
+) {
+    v.visit_ast_mut(e)
+}
+
+pub fn walk_binop_mut<V: VisitorMut + ?Sized>(
+    v: &mut V,
+    _op: &mut BinOp,
+    l: &mut Ast,
+    r: &mut Ast,
+    _loc: &mut Loc,
+) {
+    v.visit_ast_mut(l);
+    v.visit_ast_mut(r);
+}
+
+pub trait Fold {
+    fn fold_ast(&mut self, ast: Ast) -> Ast {
+        walk_fold_ast(self, ast)
+    }
+    fn fold_num(&mut self, n: u64, loc: Loc) -> Ast {
+        Ast::num(n, loc)
+    }
+    fn fold_decimal(&mut self, d: Decimal, loc: Loc) -> Ast {
+        Ast::new(AstNode::Decimal(d), loc)
+    }
+    fn fold_imaginary(&mut self, d: Decimal, loc: Loc) -> Ast {
+        Ast::new(AstNode::Imaginary(d), loc)
+    }
+    fn fold_var(&mut self, name: String, loc: Loc) -> Ast {
+        Ast::new(AstNode::Var(name), loc)
+    }
+    fn fold_uniop(&mut self, op: UniOp, e: Ast, loc: Loc) -> Ast {
+        walk_fold_uniop(self, op, e, loc)
+    }
+    fn fold_binop(&mut self, op: BinOp, l: Ast, r: Ast, loc: Loc) -> Ast {
+        walk_fold_binop(self, op, l, r, loc)
+    }
+}
+
+pub fn walk_fold_ast<F: Fold + ?Sized>(f: &mut F, mut ast: Ast) -> Ast {
+    // `AstNode` implements `Drop`, so the children are swapped out with
+    // placeholders instead of being moved out
+    let loc = ast.loc;
+    let hole = || Ast::num(0, Loc(0, 0));
+    match ast.value {
+        AstNode::Num(n) => f.fold_num(n, loc),
+        AstNode::Decimal(d) => f.fold_decimal(d, loc),
+        AstNode::Imaginary(d) => f.fold_imaginary(d, loc),
+        AstNode::Var(ref mut name) => f.fold_var(mem::take(name), loc),
+        AstNode::UniOp {
+            ref mut op,
+            ref mut e,
+        } => {
+            let op = mem::replace(op, UniOp::plus(Loc(0, 0)));
+            f.fold_uniop(op, mem::replace(e, hole()), loc)
+        }
+        AstNode::BinOp {
+            ref mut op,
+            ref mut l,
+            ref mut r,
+        } => {
+            let op = mem::replace(op, BinOp::add(Loc(0, 0)));
+            f.fold_binop(op, mem::replace(l, hole()), mem::replace(r, hole()), loc)
+        }
+    }
+}
+
+pub fn walk_fold_uniop<F: Fold + ?Sized>(f: &mut F, op: UniOp, e: Ast, loc: Loc) -> Ast {
+    let e = f.fold_ast(e);
+    Ast::uniop(op, e, loc)
+}
+
+pub fn walk_fold_binop<F: Fold + ?Sized>(f: &mut F, op: BinOp, l: Ast, r: Ast, loc: Loc) -> Ast {
+    let l = f.fold_ast(l);
+    let r = f.fold_ast(r);
+    Ast::binop(op, l, r, loc)
+}
+
+/// A step of a `Walk`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step<'a> {
+    /// Before the children of the node
+    Enter(&'a Ast),
+    /// After the children of the node
+    Leave(&'a Ast),
+}
+
+/// Depth-first iterator entering and leaving every node of an `Ast`
+pub struct Walk<'a> {
+    stack: Vec<Step<'a>>,
+}
+
+impl<'a> Walk<'a> {
+    pub fn new(ast: &'a Ast) -> Self {
+        Walk {
+            stack: vec![Step::Enter(ast)],
+        }
+    }
+
+    /// Skip the children of the node just entered, going straight to leaving it
+    pub fn skip_children(&mut self) {
+        while let Some(Step::Enter(_)) = self.stack.last() {
+            self.stack.pop();
+        }
+    }
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = Step<'a>;
+
+    fn next(&mut self) -> Option<Step<'a>> {
+        let step = self.stack.pop()?;
+        if let Step::Enter(ast) = step {
+            self.stack.push(Step::Leave(ast));
+            match ast.value {
+                AstNode::UniOp { ref e, .. } => self.stack.push(Step::Enter(e)),
+                AstNode::BinOp { ref l, ref r, .. } => {
+                    self.stack.push(Step::Enter(r));
+                    self.stack.push(Step::Enter(l));
+                }
+                _ => {}
+            }
+        }
+        Some(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::BinOpKind;
+    use crate::printer::PrettyPrinter;
+
+    #[derive(Default)]
+    struct OpCounter {
+        uniops: usize,
+        binops: usize,
+    }
+
+    impl Visitor for OpCounter {
+        fn visit_uniop(&mut self, op: &UniOp, e: &Ast, loc: &Loc) {
+            self.uniops += 1;
+            walk_uniop(self, op, e, loc)
+        }
+        fn visit_binop(&mut self, op: &BinOp, l: &Ast, r: &Ast, loc: &Loc) {
+            self.binops += 1;
+            walk_binop(self, op, l, r, loc)
+        }
+    }
+
+    struct Doubler;
+
+    impl VisitorMut for Doubler {
+        fn visit_num_mut(&mut self, n: &mut u64, _loc: &mut Loc) {
+            *n *= 2;
+        }
+    }
+
+    /// Rewrites `a - b` into `a + -b`
+    struct SubToAdd;
+
+    impl Fold for SubToAdd {
+        fn fold_binop(&mut self, op: BinOp, l: Ast, r: Ast, loc: Loc) -> Ast {
+            let l = self.fold_ast(l);
+            let r = self.fold_ast(r);
+            match op.value {
+                BinOpKind::Sub => {
+                    let r_loc = op.loc.merge(&r.loc);
+                    let r = Ast::uniop(UniOp::minus(op.loc.clone()), r, r_loc);
+                    Ast::binop(BinOp::add(op.loc), l, r, loc)
+                }
+                _ => Ast::binop(op, l, r, loc),
+            }
+        }
+    }
+
+    #[test]
+    fn test_visit() {
+        let ast = "1 + 2 * 3 - -(4 - 5)".parse::<Ast>().unwrap();
+
+        let mut counter = OpCounter::default();
+        counter.visit_ast(&ast);
+        assert_eq!((counter.uniops, counter.binops), (1, 4));
+
+        let mut doubled = ast.clone();
+        Doubler.visit_ast_mut(&mut doubled);
+        assert_eq!(
+            PrettyPrinter::new().print_flat(&doubled),
+            "2 + 4 * 6 - -(8 - 10)"
+        );
+
+        let mut counter = OpCounter::default();
+        counter.visit_ast(&SubToAdd.fold_ast(ast));
+        assert_eq!((counter.uniops, counter.binops), (3, 4));
+    }
+
+    #[test]
+    fn test_walk() {
+        let ast = "-1 * 2".parse::<Ast>().unwrap();
+        let locs = |walk: Walk| {
+            walk.map(|step| match step {
+                Step::Enter(ast) => format!("+{}", ast.loc),
+                Step::Leave(ast) => format!("-{}", ast.loc),
+            })
+            .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            locs(Walk::new(&ast)),
+            ["+0-6", "+0-2", "+1-2", "-1-2", "-0-2", "+5-6", "-5-6", "-0-6"]
+        );
+
+        let mut walk = Walk::new(&ast);
+        walk.next();
+        walk.next();
+        walk.skip_children();
+        assert_eq!(locs(walk), ["-0-2", "+5-6", "-5-6", "-0-6"]);
+    }
+}